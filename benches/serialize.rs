@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tcpoptions::{parse_options, serialize_into, serialize_options};
+
+// Linux SYN: MSS 1460, SACK permitted, timestamp, NOP, window scale 7.
+const SYN: &[u8] = &[
+    0x02, 0x04, 0x05, 0xb4, 0x04, 0x02, 0x08, 0x0a, 0x00, 0x9a, 0x2b, 0x6c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x03, 0x03, 0x07,
+];
+
+// Data segment during loss recovery: NOP, NOP, timestamp, SACK with 3 blocks.
+const SACK_SEGMENT: &[u8] = &[
+    0x01, 0x01, 0x08, 0x0a, 0x00, 0x9a, 0x2f, 0x10, 0x5d, 0x04, 0x11, 0x8e, 0x05, 0x1a, 0x7a, 0x3b,
+    0x1c, 0x00, 0x7a, 0x3b, 0x21, 0xb4, 0x7a, 0x3b, 0x27, 0x68, 0x7a, 0x3b, 0x2d, 0x1c, 0x7a, 0x3b,
+    0x32, 0xd0, 0x7a, 0x3b, 0x38, 0x84,
+];
+
+fn serialize(c: &mut Criterion) {
+    let options = parse_options(SYN);
+    let mut group = c.benchmark_group("serialize_syn");
+    group.throughput(Throughput::Bytes(SYN.len() as u64));
+    group.bench_function("serialize_options", |b| {
+        b.iter(|| serialize_options(black_box(&options)).unwrap())
+    });
+    let mut buf = Vec::with_capacity(40);
+    group.bench_function("serialize_into", |b| {
+        b.iter(|| {
+            buf.clear();
+            serialize_into(black_box(&options), &mut buf).unwrap();
+        })
+    });
+    group.finish();
+}
+
+fn parse_sack(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_sack_segment");
+    group.throughput(Throughput::Bytes(SACK_SEGMENT.len() as u64));
+    group.bench_function("parse_options", |b| b.iter(|| parse_options(black_box(SACK_SEGMENT))));
+    group.finish();
+}
+
+criterion_group!(benches, serialize, parse_sack);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+//! Decoding of the Accurate ECN option (draft-ietf-tcpm-accurate-ecn). It has
+//! two registered kinds differing in the order of their counters, and drafts
+//! also carried it in the experimental kinds under an ExID. Both forms decode
+//! into the same `AccEcn`.
+
+use crate::TcpOption;
+
+/// ExIDs that the drafts used for the two orders in the experimental kinds.
+pub const ACCECN_ORDER0_EXPERIMENT_ID: u16 = 0xACC0;
+pub const ACCECN_ORDER1_EXPERIMENT_ID: u16 = 0xACC1;
+
+/// The byte counters of an AccECN option. Each is 24 bits on the wire; a
+/// counter the sender left out is None. Order 0 sends EE0B, ECEB, EE1B and
+/// order 1 sends EE1B, ECEB, EE0B, in both cases truncated from the end.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct AccEcn {
+    pub order: u8,
+    pub ee0b: Option<u32>,
+    pub eceb: Option<u32>,
+    pub ee1b: Option<u32>,
+}
+
+impl AccEcn {
+    /// Decodes the counters of an option of the given order, without the
+    /// kind, length or ExID bytes. The payload holds up to three 3-byte
+    /// counters.
+    pub fn parse(order: u8, payload: &[u8]) -> Option<AccEcn> {
+        if !payload.len().is_multiple_of(3) || payload.len() > 9 {
+            return None;
+        }
+        let mut counters = payload
+            .chunks_exact(3)
+            .map(|counter| u32::from_be_bytes([0, counter[0], counter[1], counter[2]]));
+        let (first, eceb, last) = (counters.next(), counters.next(), counters.next());
+        let (ee0b, ee1b) = if order == 0 { (first, last) } else { (last, first) };
+        Some(AccEcn { order, ee0b, eceb, ee1b })
+    }
+
+    /// Decodes the option if it is an AccECN option under its registered kind
+    /// or under an AccECN ExID in an experimental kind.
+    pub fn from_option(option: &TcpOption) -> Option<AccEcn> {
+        match option {
+            TcpOption::AccECNOrder0(payload) => AccEcn::parse(0, payload),
+            TcpOption::AccECNOrder1(payload) => AccEcn::parse(1, payload),
+            TcpOption::RFC3692Experiment1(payload) | TcpOption::RFC3692Experiment2(payload) => {
+                let (exid, counters) = payload.split_at_checked(2)?;
+                match u16::from_be_bytes([exid[0], exid[1]]) {
+                    ACCECN_ORDER0_EXPERIMENT_ID => AccEcn::parse(0, counters),
+                    ACCECN_ORDER1_EXPERIMENT_ID => AccEcn::parse(1, counters),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedicated_kind_and_experiment_forms_decode_alike() {
+        let counters = [0, 0, 1, 0, 0, 2, 0, 0, 3];
+        let dedicated = AccEcn::from_option(&TcpOption::AccECNOrder0(counters.to_vec())).unwrap();
+        let experiment = AccEcn::from_option(&TcpOption::RFC3692Experiment1([&[0xac, 0xc0], &counters[..]].concat()));
+        assert_eq!(experiment, Some(dedicated));
+        assert_eq!(dedicated, AccEcn { order: 0, ee0b: Some(1), eceb: Some(2), ee1b: Some(3) });
+
+        let dedicated = AccEcn::from_option(&TcpOption::AccECNOrder1(counters[..3].to_vec())).unwrap();
+        let experiment = AccEcn::from_option(&TcpOption::RFC3692Experiment2(vec![0xac, 0xc1, 0, 0, 1]));
+        assert_eq!(experiment, Some(dedicated));
+        assert_eq!(dedicated, AccEcn { order: 1, ee0b: None, eceb: None, ee1b: Some(1) });
+
+        assert_eq!(AccEcn::from_option(&TcpOption::RFC3692Experiment1(vec![0x12, 0x34, 0, 0, 1])), None);
+    }
+}
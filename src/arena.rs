@@ -0,0 +1,36 @@
+//! Parsing into a caller-provided bump arena, so a flow processor can reuse
+//! one arena per packet and reset it instead of going through the allocator
+//! for every options list.
+
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+
+use crate::{walk, TcpOption};
+
+/// Parses the field like `parse_options`, storing the list in `arena`. The
+/// result derefs to `&[TcpOption]`. It is a bump-allocated vector rather than
+/// a bare slice so that dropping it still frees the payloads of
+/// variable-length options, which live on the heap; the arena never runs
+/// destructors. Drop the result before resetting the arena.
+pub fn parse_options_in<'a>(data: &[u8], arena: &'a Bump) -> BumpVec<'a, TcpOption> {
+    let mut options = BumpVec::new_in(arena);
+    options.extend(walk(data));
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vectors::{linux_syn_expected, LINUX_SYN};
+
+    #[test]
+    fn options_are_read_from_the_arena_and_it_resets() {
+        let mut arena = Bump::new();
+        for _ in 0..2 {
+            let options = parse_options_in(LINUX_SYN, &arena);
+            assert_eq!(&options[..], &linux_syn_expected()[..]);
+            drop(options);
+            arena.reset();
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Identification of the experiment carried in the experimental kinds 253 and
+//! 254 by its 16-bit ExID (RFC 6994). The registry starts out with the ExIDs
+//! this crate knows and can be extended with others at run time.
+
+use std::collections::BTreeMap;
+
+use crate::accecn::{ACCECN_ORDER0_EXPERIMENT_ID, ACCECN_ORDER1_EXPERIMENT_ID};
+use crate::{TcpOption, TFO_EXPERIMENT_ID};
+
+// ExIDs from the IANA TCP Experimental Option Experiment Identifiers
+// registry that `ExperimentRegistry::default` knows.
+const KNOWN_EXPERIMENTS: &[(u16, &str)] = &[
+    (0x0348, "HOST_ID"),
+    (0x454E, "TCP-ENO"),
+    (ACCECN_ORDER0_EXPERIMENT_ID, "AccECN order 0"),
+    (ACCECN_ORDER1_EXPERIMENT_ID, "AccECN order 1"),
+    (TFO_EXPERIMENT_ID, "TCP Fast Open"),
+];
+
+/// An experimental option split into its ExID and the data after it. `name`
+/// is None for an ExID the registry does not know.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Experiment<'a> {
+    pub exid: u16,
+    pub name: Option<&'static str>,
+    pub data: &'a [u8],
+}
+
+/// Names of experiments by ExID.
+#[derive(Debug,Clone)]
+pub struct ExperimentRegistry {
+    names: BTreeMap<u16, &'static str>,
+}
+
+impl Default for ExperimentRegistry {
+    fn default() -> Self {
+        Self { names: KNOWN_EXPERIMENTS.iter().copied().collect() }
+    }
+}
+
+impl ExperimentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names an ExID, replacing any name it already had.
+    pub fn register(&mut self, exid: u16, name: &'static str) -> &mut Self {
+        self.names.insert(exid, name);
+        self
+    }
+
+    pub fn name(&self, exid: u16) -> Option<&'static str> {
+        self.names.get(&exid).copied()
+    }
+
+    /// Splits an experimental option into its ExID and data and names it if
+    /// the ExID is registered. None for any other option.
+    pub fn identify<'a>(&self, option: &'a TcpOption) -> Option<Experiment<'a>> {
+        let exid = option.experiment_id()?;
+        let (TcpOption::RFC3692Experiment1(payload) | TcpOption::RFC3692Experiment2(payload)) = option else {
+            return None;
+        };
+        Some(Experiment { exid, name: self.name(exid), data: &payload[2..] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_names_known_and_registered_exids() {
+        let mut registry = ExperimentRegistry::new();
+        let known = TcpOption::RFC3692Experiment2(vec![0xf9, 0x89, 1, 2]);
+        assert_eq!(
+            registry.identify(&known),
+            Some(Experiment { exid: TFO_EXPERIMENT_ID, name: Some("TCP Fast Open"), data: &[1, 2] })
+        );
+
+        let unknown = TcpOption::RFC3692Experiment1(vec![0x12, 0x34, 5]);
+        assert_eq!(registry.identify(&unknown), Some(Experiment { exid: 0x1234, name: None, data: &[5] }));
+        registry.register(0x1234, "Local experiment");
+        assert_eq!(registry.identify(&unknown).unwrap().name, Some("Local experiment"));
+
+        assert_eq!(registry.identify(&TcpOption::NoOperation), None);
+    }
+}
@@ -0,0 +1,92 @@
+//! JSON rendering of parsed options as an object keyed by option name, for
+//! dashboards and other consumers that look options up by name.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::TcpOption;
+
+/// Renders the options as an object mapping each option's name to its value,
+/// for example `{"MaximumSegmentSize": 1460, "SackPermitted": true}`. Options
+/// without a value map to `true`, byte payloads and 128-bit values to hex
+/// strings. A name that occurs more than once maps to an array of its values
+/// in field order.
+pub fn to_json_named(opts: &[TcpOption]) -> Value {
+    let mut values: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+    for option in opts {
+        values.entry(option.name()).or_default().push(option_value(option));
+    }
+    let object: Map<String, Value> = values
+        .into_iter()
+        .map(|(name, mut values)| {
+            let value = if values.len() == 1 { values.remove(0) } else { Value::Array(values) };
+            (name.to_string(), value)
+        })
+        .collect();
+    Value::Object(object)
+}
+
+fn option_value(option: &TcpOption) -> Value {
+    match option {
+        TcpOption::MaximumSegmentSize(value) | TcpOption::UserTimeout(value) => json!(value),
+        TcpOption::WindowScale(shift) => json!(shift.raw()),
+        TcpOption::PartialOrderServiceProfile(value)
+        | TcpOption::AlternateChecksumRequest(value)
+        | TcpOption::TrailerChecksum(value) => json!(value),
+        TcpOption::Echo(value)
+        | TcpOption::EchoReply(value)
+        | TcpOption::ConnectionCount(value)
+        | TcpOption::ConnectionCountNew(value)
+        | TcpOption::ConnectionCountEcho(value) => json!(value),
+        TcpOption::QuickStartResponse(value) => json!(value),
+        TcpOption::Timestamp(timestamp) => {
+            json!({ "value": timestamp.value(), "echo_reply": timestamp.echo_reply() })
+        }
+        TcpOption::Sack(sacks) => sacks
+            .iter()
+            .map(|sack| json!({ "left_edge": sack.left_edge(), "right_edge": sack.right_edge() }))
+            .collect(),
+        TcpOption::MD5Signature(value) | TcpOption::TCPFastOpenCookie(value) => json!(format!("{:032x}", value)),
+        TcpOption::AlternateChecksumData(data)
+        | TcpOption::Skeeter(data)
+        | TcpOption::Bubba(data)
+        | TcpOption::SCPSCapabilities(data)
+        | TcpOption::SelectiveNegativeAcknowledgements(data)
+        | TcpOption::SNAP(data)
+        | TcpOption::TCPCompressionFilter(data)
+        | TcpOption::TCPAuthenticationOption(data)
+        | TcpOption::MultipathTCP(data)
+        | TcpOption::EncryptionNegotiation(data)
+        | TcpOption::AccECNOrder0(data)
+        | TcpOption::AccECNOrder1(data)
+        | TcpOption::RFC3692Experiment1(data)
+        | TcpOption::RFC3692Experiment2(data) => json!(hex(data)),
+        TcpOption::TruncatedKind(kind) => json!({ "kind": kind }),
+        TcpOption::Unknown { kind, data, .. } => json!({ "kind": kind, "data": hex(data) }),
+        _ => json!(true),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vectors::windows_syn_expected;
+
+    #[test]
+    fn syn_renders_keyed_by_name() {
+        assert_eq!(
+            to_json_named(&windows_syn_expected()),
+            json!({
+                "MaximumSegmentSize": 1460,
+                "NoOperation": [true, true, true],
+                "WindowScale": 8,
+                "SackPermitted": true,
+            })
+        );
+    }
+}
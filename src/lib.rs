@@ -1,284 +1,2619 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::collections::BTreeMap;
+use std::fmt;
 
-use num_enum::FromPrimitive;
-use once_cell::sync::Lazy;
+pub mod accecn;
+pub mod experiment;
+pub mod mptcp;
+pub mod seq;
+#[cfg(feature = "arena")]
+pub mod arena;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod test_vectors;
 
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub struct Sack {
     left_edge: u32,
     right_edge: u32,
 }
 
-#[derive(Debug,Clone,Copy)]
+impl Sack {
+    pub fn new(left_edge: u32, right_edge: u32) -> Self {
+        Self { left_edge, right_edge }
+    }
+
+    pub fn left_edge(&self) -> u32 {
+        self.left_edge
+    }
+
+    pub fn right_edge(&self) -> u32 {
+        self.right_edge
+    }
+
+    /// Number of sequence numbers the block covers, modulo 2^32, so a block
+    /// spanning the wrap is counted correctly. A block whose right edge
+    /// comes before its left edge yields the wrapped difference, not a panic.
+    pub fn len(&self) -> u32 {
+        self.right_edge.wrapping_sub(self.left_edge)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left_edge == self.right_edge
+    }
+}
+
+impl fmt::Display for Sack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.left_edge, self.right_edge)
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub struct Timestamp {
     value: u32,
     echo_reply: u32,
 }
 
-#[derive(Debug,FromPrimitive,Clone,Copy)]
+impl Timestamp {
+    pub fn new(value: u32, echo_reply: u32) -> Self {
+        Self { value, echo_reply }
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn echo_reply(&self) -> u32 {
+        self.echo_reply
+    }
+}
+
+/// A window scale shift count. RFC 7323 caps the shift at 14, so larger values
+/// cannot be constructed.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct WindowShift(u8);
+
+impl WindowShift {
+    pub const MAX: u8 = 14;
+
+    pub fn new(shift: u8) -> Option<Self> {
+        if shift > Self::MAX {
+            return None;
+        }
+        Some(Self(shift))
+    }
+
+    pub fn raw(&self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
 #[repr(u8)]
 pub enum TcpOption {
     EndOfOptionList = 0,
     NoOperation = 1,
     MaximumSegmentSize(u16) = 2,
-    WindowScale(u8) = 3,
+    WindowScale(WindowShift) = 3,
     SackPermitted = 4,
     Sack(Vec<Sack>) = 5,
+    /// RFC 1072; obsoleted by Timestamp.
+    Echo(u32) = 6,
+    /// RFC 1072; obsoleted by Timestamp.
+    EchoReply(u32) = 7,
     Timestamp(Timestamp) = 8,
-    Skeeter = 16,
-    Bubba = 17,
+    /// RFC 1693; historic (RFC 6247).
+    PartialOrderConnectionPermitted = 9,
+    /// RFC 1693; historic (RFC 6247).
+    PartialOrderServiceProfile(u8) = 10,
+    /// RFC 1644 (T/TCP); historic (RFC 6247).
+    ConnectionCount(u32) = 11,
+    /// RFC 1644 (T/TCP); historic (RFC 6247).
+    ConnectionCountNew(u32) = 12,
+    /// RFC 1644 (T/TCP); historic (RFC 6247).
+    ConnectionCountEcho(u32) = 13,
+    /// RFC 1146; historic (RFC 6247).
+    AlternateChecksumRequest(u8) = 14,
+    /// RFC 1146; historic (RFC 6247).
+    AlternateChecksumData(Vec<u8>) = 15,
+    Skeeter(Vec<u8>) = 16,
+    Bubba(Vec<u8>) = 17,
     TrailerChecksum(u8) = 18,
-    SCPSCapabilities = 20,
-    SelectiveNegativeAcknowledgements = 21,
+    /// RFC 2385; obsoleted by TCP-AO.
+    MD5Signature(u128) = 19,
+    SCPSCapabilities(Vec<u8>) = 20,
+    SelectiveNegativeAcknowledgements(Vec<u8>) = 21,
     RecordBoundaries = 22,
     CorruptionExperienced = 23,
-    SNAP = 24,
-    TCPCompressionFilter = 26,
+    SNAP(Vec<u8>) = 24,
+    TCPCompressionFilter(Vec<u8>) = 26,
     QuickStartResponse(u64) = 27,
     UserTimeout(u16) = 28,
-    TCPAuthenticationOption = 29,
-    MultipathTCP(Vec<u8>) = 30, // TODO: Deserialize this better
+    TCPAuthenticationOption(Vec<u8>) = 29,
+    /// The raw MPTCP payload, starting at the subtype byte. Decode it with
+    /// `mptcp::Dss` or `mptcp::MpCapable`.
+    MultipathTCP(Vec<u8>) = 30,
     TCPFastOpenCookie(u128) = 34,
     EncryptionNegotiation(Vec<u8>) = 69, // TODO: Deserialize this better
-    AccECNOrder0(Vec<u8>) = 172,         // Newly registered, needs deserialization
-    AccECNOrder1(Vec<u8>) = 174,         // Newly registered, needs deserialization
-    RFC3692Experiment1(Vec<u8>) = 253,   // Experimental, needs deserialization
-    RFC3692Experiment2(Vec<u8>) = 254,   // Experimental, needs deserialization
+    /// The raw AccECN counters in order 0. Decode them with `accecn::AccEcn`.
+    AccECNOrder0(Vec<u8>) = 172,
+    /// The raw AccECN counters in order 1. Decode them with `accecn::AccEcn`.
+    AccECNOrder1(Vec<u8>) = 174,
+    /// A kind byte that ends the field with no room for its length byte, kept
+    /// by `ParseConfig::wire_faithful` and `parse_with_diagnostics` so the
+    /// field can be written back byte for byte. It encodes as the lone kind
+    /// byte.
+    TruncatedKind(u8),
+    /// An RFC 3692 experiment, starting with its ExID. Read the ExID with
+    /// `TcpOption::experiment_id` and name it with
+    /// `experiment::ExperimentRegistry`.
+    RFC3692Experiment1(Vec<u8>) = 253,
+    /// Like `RFC3692Experiment1`, under the second experimental kind.
+    RFC3692Experiment2(Vec<u8>) = 254,
+    /// Any kind without a parser. `length` holds a declared length byte that
+    /// disagrees with the payload, as salvaged by `parse_with_diagnostics`, so
+    /// the option can be written back exactly as it was received.
+    Unknown { kind: u8, length: Option<u8>, data: Vec<u8> } = 255,
+}
+
+/// Rough size buckets for an option's encoded length.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum SizeClass {
+    /// A lone kind byte.
+    Single,
+    /// Up to 4 bytes.
+    Small,
+    /// Up to 10 bytes.
+    Medium,
+    /// More than 10 bytes.
+    Large,
+}
+
+/// Broad purpose of an option.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum OptionCategory {
+    /// End of option list and NOP.
+    Padding,
+    /// Connection parameters: MSS, window scale, SACK permitted, user timeout.
+    Negotiation,
+    /// SACK blocks and SNACK.
+    LossRecovery,
+    /// Timestamps.
+    Timing,
+    /// Authentication and encryption negotiation.
+    Security,
+    Multipath,
+    FastOpen,
+    Ecn,
+    /// RFC 3692 experiments.
+    Experimental,
+    /// Historic or rarely deployed options.
+    Other,
 }
 
-type OptionParser = Box<dyn Fn(&[u8]) -> Option<TcpOption> + Send + Sync>;
+impl TcpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            TcpOption::EndOfOptionList => 0,
+            TcpOption::NoOperation => 1,
+            TcpOption::MaximumSegmentSize(_) => 2,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 4,
+            TcpOption::Sack(_) => 5,
+            TcpOption::Echo(_) => 6,
+            TcpOption::EchoReply(_) => 7,
+            TcpOption::Timestamp(_) => 8,
+            TcpOption::PartialOrderConnectionPermitted => 9,
+            TcpOption::PartialOrderServiceProfile(_) => 10,
+            TcpOption::ConnectionCount(_) => 11,
+            TcpOption::ConnectionCountNew(_) => 12,
+            TcpOption::ConnectionCountEcho(_) => 13,
+            TcpOption::AlternateChecksumRequest(_) => 14,
+            TcpOption::AlternateChecksumData(_) => 15,
+            TcpOption::Skeeter(_) => 16,
+            TcpOption::Bubba(_) => 17,
+            TcpOption::TrailerChecksum(_) => 18,
+            TcpOption::MD5Signature(_) => 19,
+            TcpOption::SCPSCapabilities(_) => 20,
+            TcpOption::SelectiveNegativeAcknowledgements(_) => 21,
+            TcpOption::RecordBoundaries => 22,
+            TcpOption::CorruptionExperienced => 23,
+            TcpOption::SNAP(_) => 24,
+            TcpOption::TCPCompressionFilter(_) => 26,
+            TcpOption::QuickStartResponse(_) => 27,
+            TcpOption::UserTimeout(_) => 28,
+            TcpOption::TCPAuthenticationOption(_) => 29,
+            TcpOption::MultipathTCP(_) => 30,
+            TcpOption::TCPFastOpenCookie(_) => 34,
+            TcpOption::EncryptionNegotiation(_) => 69,
+            TcpOption::AccECNOrder0(_) => 172,
+            TcpOption::AccECNOrder1(_) => 174,
+            TcpOption::RFC3692Experiment1(_) => 253,
+            TcpOption::RFC3692Experiment2(_) => 254,
+            TcpOption::TruncatedKind(kind) | TcpOption::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// A timestamp option whose TSval is the milliseconds elapsed on a
+    /// monotonic clock since the first call in this process, wrapping at 2^32.
+    #[cfg(feature = "std")]
+    pub fn timestamp_now(echo_reply: u32) -> TcpOption {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        let value = START.get_or_init(std::time::Instant::now).elapsed().as_millis() as u32;
+        TcpOption::Timestamp(Timestamp { value, echo_reply })
+    }
+
+    /// The ExID of an experimental option, such as `TFO_EXPERIMENT_ID` for a
+    /// Fast Open option sent before RFC 7413.
+    pub fn experiment_id(&self) -> Option<u16> {
+        match self {
+            TcpOption::RFC3692Experiment1(data) | TcpOption::RFC3692Experiment2(data) if data.len() >= 2 => {
+                Some(u16::from_be_bytes([data[0], data[1]]))
+            }
+            _ => None,
+        }
+    }
+
+    /// The cookie of a Fast Open option, either under kind 34 or under
+    /// `TFO_EXPERIMENT_ID` in an experimental kind. The experimental form may
+    /// carry a cookie of any length, and an empty one is a cookie request.
+    /// None for any other option.
+    pub fn fast_open_cookie(&self) -> Option<Vec<u8>> {
+        match self {
+            TcpOption::TCPFastOpenCookie(cookie) => Some(cookie.to_be_bytes().to_vec()),
+            option if option.experiment_id() == Some(TFO_EXPERIMENT_ID) => {
+                let (TcpOption::RFC3692Experiment1(data) | TcpOption::RFC3692Experiment2(data)) = option else {
+                    return None;
+                };
+                Some(data[2..].to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    /// The blocks of a SACK option, or an empty slice for any other option.
+    pub fn sack_blocks(&self) -> &[Sack] {
+        match self {
+            TcpOption::Sack(sacks) => sacks,
+            _ => &[],
+        }
+    }
+
+    pub fn sacks_iter(&self) -> impl Iterator<Item = &Sack> {
+        self.sack_blocks().iter()
+    }
+
+    /// Sorts the blocks of a SACK option by left edge. Edges are compared as
+    /// sequence numbers, so the order holds across the 2^32 wrap as long as
+    /// all blocks lie within 2^31 of each other. Other options are untouched.
+    pub fn sort_sack_blocks(&mut self) {
+        let TcpOption::Sack(sacks) = self else {
+            return;
+        };
+        let Some(first) = sacks.first() else {
+            return;
+        };
+        let earliest = sacks.iter().fold(first.left_edge, |earliest, sack| {
+            if seq::seq_lt(sack.left_edge, earliest) {
+                sack.left_edge
+            } else {
+                earliest
+            }
+        });
+        sacks.sort_by_key(|sack| sack.left_edge.wrapping_sub(earliest));
+    }
+
+    pub fn category(&self) -> OptionCategory {
+        match self {
+            TcpOption::EndOfOptionList | TcpOption::NoOperation => OptionCategory::Padding,
+            TcpOption::MaximumSegmentSize(_)
+            | TcpOption::WindowScale(_)
+            | TcpOption::SackPermitted
+            | TcpOption::UserTimeout(_) => OptionCategory::Negotiation,
+            TcpOption::Sack(_) | TcpOption::SelectiveNegativeAcknowledgements(_) => OptionCategory::LossRecovery,
+            TcpOption::Timestamp(_) => OptionCategory::Timing,
+            TcpOption::MD5Signature(_)
+            | TcpOption::TCPAuthenticationOption(_)
+            | TcpOption::EncryptionNegotiation(_) => OptionCategory::Security,
+            TcpOption::MultipathTCP(_) => OptionCategory::Multipath,
+            TcpOption::TCPFastOpenCookie(_) => OptionCategory::FastOpen,
+            TcpOption::AccECNOrder0(_) | TcpOption::AccECNOrder1(_) => OptionCategory::Ecn,
+            TcpOption::RFC3692Experiment1(_) | TcpOption::RFC3692Experiment2(_) => OptionCategory::Experimental,
+            _ => OptionCategory::Other,
+        }
+    }
+
+    /// Options that are only meaningful on SYN and SYN-ACK segments.
+    pub fn is_syn_only(&self) -> bool {
+        matches!(
+            self,
+            TcpOption::MaximumSegmentSize(_)
+                | TcpOption::WindowScale(_)
+                | TcpOption::SackPermitted
+                | TcpOption::TCPFastOpenCookie(_)
+        )
+    }
+
+    /// Compares two options like `==`, except that SACK options are equal when
+    /// they carry the same blocks in any order.
+    pub fn sack_eq_unordered(&self, other: &Self) -> bool {
+        let (TcpOption::Sack(a), TcpOption::Sack(b)) = (self, other) else {
+            return self == other;
+        };
+        let edges = |sacks: &[Sack]| {
+            let mut edges: Vec<(u32, u32)> = sacks.iter().map(|sack| (sack.left_edge, sack.right_edge)).collect();
+            edges.sort_unstable();
+            edges
+        };
+        a.len() == b.len() && edges(a) == edges(b)
+    }
+
+    /// Options that change how either end sends or accepts segments, as
+    /// opposed to padding and informational or historic options. Useful when
+    /// checking which option rewrites by a middlebox actually matter.
+    pub fn is_behavioral(&self) -> bool {
+        matches!(
+            self,
+            TcpOption::MaximumSegmentSize(_)
+                | TcpOption::WindowScale(_)
+                | TcpOption::SackPermitted
+                | TcpOption::Sack(_)
+                | TcpOption::Timestamp(_)
+                | TcpOption::UserTimeout(_)
+                | TcpOption::MD5Signature(_)
+                | TcpOption::TCPAuthenticationOption(_)
+                | TcpOption::MultipathTCP(_)
+                | TcpOption::TCPFastOpenCookie(_)
+                | TcpOption::EncryptionNegotiation(_)
+                | TcpOption::AccECNOrder0(_)
+                | TcpOption::AccECNOrder1(_)
+        )
+    }
+
+    /// Checks the invariants a well-formed option of this kind must hold, for
+    /// options built by hand before they are serialized. An `Unknown` of a kind
+    /// the crate parses must parse as that kind, with a window scale of at
+    /// most 14.
+    pub fn is_valid(&self) -> bool {
+        if self.encoded_len() > 40 {
+            return false;
+        }
+        match self {
+            TcpOption::MaximumSegmentSize(mss) => *mss != 0,
+            TcpOption::Sack(sacks) => {
+                !sacks.is_empty()
+                    && sacks.len() <= 4
+                    && sacks.iter().all(|sack| seq::seq_lt(sack.left_edge, sack.right_edge))
+            }
+            TcpOption::TCPAuthenticationOption(data)
+            | TcpOption::MultipathTCP(data)
+            | TcpOption::EncryptionNegotiation(data)
+            | TcpOption::RFC3692Experiment1(data)
+            | TcpOption::RFC3692Experiment2(data) => data.len() >= 2,
+            TcpOption::AccECNOrder0(data) | TcpOption::AccECNOrder1(data) => data.len().is_multiple_of(3) && data.len() <= 9,
+            TcpOption::TruncatedKind(_) => false,
+            TcpOption::Unknown { kind, length, data } => {
+                let framed = length.is_none_or(|length| length as usize == self.encoded_len());
+                let parses = match parser_for(*kind) {
+                    Some(parser) => {
+                        parser(&self.to_bytes()).is_ok()
+                            && !(*kind == 3 && data.first().is_some_and(|&shift| shift > WindowShift::MAX))
+                    }
+                    None => true,
+                };
+                framed && parses
+            }
+            _ => true,
+        }
+    }
+
+    /// The variant name, e.g. "MaximumSegmentSize".
+    pub fn name(&self) -> &'static str {
+        match self {
+            TcpOption::EndOfOptionList => "EndOfOptionList",
+            TcpOption::NoOperation => "NoOperation",
+            TcpOption::MaximumSegmentSize(_) => "MaximumSegmentSize",
+            TcpOption::WindowScale(_) => "WindowScale",
+            TcpOption::SackPermitted => "SackPermitted",
+            TcpOption::Sack(_) => "Sack",
+            TcpOption::Echo(_) => "Echo",
+            TcpOption::EchoReply(_) => "EchoReply",
+            TcpOption::Timestamp(_) => "Timestamp",
+            TcpOption::PartialOrderConnectionPermitted => "PartialOrderConnectionPermitted",
+            TcpOption::PartialOrderServiceProfile(_) => "PartialOrderServiceProfile",
+            TcpOption::ConnectionCount(_) => "ConnectionCount",
+            TcpOption::ConnectionCountNew(_) => "ConnectionCountNew",
+            TcpOption::ConnectionCountEcho(_) => "ConnectionCountEcho",
+            TcpOption::AlternateChecksumRequest(_) => "AlternateChecksumRequest",
+            TcpOption::AlternateChecksumData(_) => "AlternateChecksumData",
+            TcpOption::Skeeter(_) => "Skeeter",
+            TcpOption::Bubba(_) => "Bubba",
+            TcpOption::TrailerChecksum(_) => "TrailerChecksum",
+            TcpOption::MD5Signature(_) => "MD5Signature",
+            TcpOption::SCPSCapabilities(_) => "SCPSCapabilities",
+            TcpOption::SelectiveNegativeAcknowledgements(_) => "SelectiveNegativeAcknowledgements",
+            TcpOption::RecordBoundaries => "RecordBoundaries",
+            TcpOption::CorruptionExperienced => "CorruptionExperienced",
+            TcpOption::SNAP(_) => "SNAP",
+            TcpOption::TCPCompressionFilter(_) => "TCPCompressionFilter",
+            TcpOption::QuickStartResponse(_) => "QuickStartResponse",
+            TcpOption::UserTimeout(_) => "UserTimeout",
+            TcpOption::TCPAuthenticationOption(_) => "TCPAuthenticationOption",
+            TcpOption::MultipathTCP(_) => "MultipathTCP",
+            TcpOption::TCPFastOpenCookie(_) => "TCPFastOpenCookie",
+            TcpOption::EncryptionNegotiation(_) => "EncryptionNegotiation",
+            TcpOption::AccECNOrder0(_) => "AccECNOrder0",
+            TcpOption::AccECNOrder1(_) => "AccECNOrder1",
+            TcpOption::RFC3692Experiment1(_) => "RFC3692Experiment1",
+            TcpOption::RFC3692Experiment2(_) => "RFC3692Experiment2",
+            TcpOption::TruncatedKind(_) => "TruncatedKind",
+            TcpOption::Unknown { .. } => "Unknown",
+        }
+    }
 
+    /// Number of bytes the option occupies on the wire, including the kind and
+    /// length bytes. This is the value written to the length byte: RFC 9293
+    /// counts the framing in the length, and the parsers likewise receive the
+    /// whole option and read the payload from index 2.
+    pub fn encoded_len(&self) -> usize {
+        if self.is_lone_kind_byte() {
+            return 1;
+        }
+        match self {
+            TcpOption::MaximumSegmentSize(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::Sack(sacks) => 2 + sacks.len() * 8,
+            TcpOption::Echo(_)
+            | TcpOption::EchoReply(_)
+            | TcpOption::ConnectionCount(_)
+            | TcpOption::ConnectionCountNew(_)
+            | TcpOption::ConnectionCountEcho(_) => 6,
+            TcpOption::Timestamp(_) => 10,
+            TcpOption::PartialOrderServiceProfile(_)
+            | TcpOption::AlternateChecksumRequest(_)
+            | TcpOption::TrailerChecksum(_) => 3,
+            TcpOption::MD5Signature(_) => 18,
+            TcpOption::QuickStartResponse(_) => 8,
+            TcpOption::UserTimeout(_) => 4,
+            TcpOption::TCPFastOpenCookie(_) => 18,
+            TcpOption::AlternateChecksumData(data)
+            | TcpOption::Skeeter(data)
+            | TcpOption::Bubba(data)
+            | TcpOption::SCPSCapabilities(data)
+            | TcpOption::SelectiveNegativeAcknowledgements(data)
+            | TcpOption::SNAP(data)
+            | TcpOption::TCPCompressionFilter(data)
+            | TcpOption::TCPAuthenticationOption(data)
+            | TcpOption::MultipathTCP(data)
+            | TcpOption::EncryptionNegotiation(data)
+            | TcpOption::AccECNOrder0(data)
+            | TcpOption::AccECNOrder1(data)
+            | TcpOption::RFC3692Experiment1(data)
+            | TcpOption::RFC3692Experiment2(data)
+            | TcpOption::Unknown { data, .. } => 2 + data.len(),
+            _ => 2,
+        }
+    }
 
+    pub fn size_class(&self) -> SizeClass {
+        match self.encoded_len() {
+            1 => SizeClass::Single,
+            2..=4 => SizeClass::Small,
+            5..=10 => SizeClass::Medium,
+            _ => SizeClass::Large,
+        }
+    }
 
+    /// Encodes the option as it appears on the wire. Single-byte kinds are
+    /// emitted as just the kind byte, so repeated NOPs stay repeated.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_to(&mut bytes);
+        bytes
+    }
 
-// Define the static map with closures wrapped in a Box for dynamic dispatch.
-static OPTION_PARSERS: Lazy<HashMap<u8, OptionParser>> = Lazy::new(|| {
-    let mut parsers: HashMap<u8, OptionParser> = HashMap::new();
+    /// Encodes the option with `len` as its length byte in place of the real
+    /// one, for crafting malformed options to test other stacks. A receiver
+    /// will misframe everything after the option, so the output must not be
+    /// sent to hosts that are not under test. Single-byte kinds get the
+    /// length byte appended.
+    pub fn to_bytes_with_length(&self, len: u8) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        match bytes.get_mut(1) {
+            Some(length) => *length = len,
+            None => bytes.push(len),
+        }
+        bytes
+    }
 
-    // NoOperation parser
-    parsers.insert(1, Box::new(|_: &[u8]| Some(TcpOption::NoOperation)));
+    /// Encodes the option, reusing the payload buffer of variable-length
+    /// options for the output instead of copying it. The output always
+    /// matches `to_bytes`.
+    pub fn into_bytes(self) -> Vec<u8> {
+        if self.is_lone_kind_byte() {
+            return self.to_bytes();
+        }
+        let kind = self.kind();
+        let len = self.length_byte();
+        match self {
+            TcpOption::AlternateChecksumData(mut data)
+            | TcpOption::Skeeter(mut data)
+            | TcpOption::Bubba(mut data)
+            | TcpOption::SCPSCapabilities(mut data)
+            | TcpOption::SelectiveNegativeAcknowledgements(mut data)
+            | TcpOption::SNAP(mut data)
+            | TcpOption::TCPCompressionFilter(mut data)
+            | TcpOption::TCPAuthenticationOption(mut data)
+            | TcpOption::MultipathTCP(mut data)
+            | TcpOption::EncryptionNegotiation(mut data)
+            | TcpOption::AccECNOrder0(mut data)
+            | TcpOption::AccECNOrder1(mut data)
+            | TcpOption::RFC3692Experiment1(mut data)
+            | TcpOption::RFC3692Experiment2(mut data)
+            | TcpOption::Unknown { mut data, .. } => {
+                data.splice(0..0, [kind, len]);
+                data
+            }
+            option => option.to_bytes(),
+        }
+    }
+
+    /// Appends the encoded option to `bytes`.
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        self.encode(|chunk| bytes.extend_from_slice(chunk));
+    }
+
+    // Single-byte kinds and a truncated kind byte are written without a
+    // length byte.
+    fn is_lone_kind_byte(&self) -> bool {
+        matches!(self, TcpOption::TruncatedKind(_)) || is_single_byte_kind(self.kind())
+    }
 
-    // MaximumSegmentSize parser
-    parsers.insert(
-        2,
-        Box::new(|data: &[u8]| {
-            if data.len() != 4 {
-                return None;
+    // The length byte to write: the encoded length, unless an `Unknown`
+    // option carries the length it was received with.
+    fn length_byte(&self) -> u8 {
+        match self {
+            TcpOption::Unknown { length: Some(length), .. } => *length,
+            _ => self.encoded_len() as u8,
+        }
+    }
+
+    // Hands the encoded option to `emit` a piece at a time, so callers can
+    // write it into any buffer.
+    fn encode(&self, mut emit: impl FnMut(&[u8])) {
+        if self.is_lone_kind_byte() {
+            emit(&[self.kind()]);
+            return;
+        }
+        emit(&[self.kind(), self.length_byte()]);
+        match self {
+            TcpOption::MaximumSegmentSize(mss) => emit(&mss.to_be_bytes()),
+            TcpOption::WindowScale(shift) => emit(&[shift.raw()]),
+            TcpOption::PartialOrderServiceProfile(value)
+            | TcpOption::AlternateChecksumRequest(value)
+            | TcpOption::TrailerChecksum(value) => emit(&[*value]),
+            TcpOption::Echo(value)
+            | TcpOption::EchoReply(value)
+            | TcpOption::ConnectionCount(value)
+            | TcpOption::ConnectionCountNew(value)
+            | TcpOption::ConnectionCountEcho(value) => emit(&value.to_be_bytes()),
+            TcpOption::MD5Signature(digest) => emit(&digest.to_be_bytes()),
+            TcpOption::Sack(sacks) => {
+                for sack in sacks {
+                    emit(&sack.left_edge.to_be_bytes());
+                    emit(&sack.right_edge.to_be_bytes());
+                }
             }
-            let mss = {
-                let mut mss_bytes = [0u8; 2];
-                mss_bytes.copy_from_slice(&data[2..data.len() as usize]);
-                u16::from_be_bytes(mss_bytes)
-            };
-            Some(TcpOption::MaximumSegmentSize(mss))
-        }),
-    );
-
-    // WindowScale parser
-    parsers.insert(
-        3,
-        Box::new(|data: &[u8]| {
-            if data.len() != 3 {
-                return None;
+            TcpOption::Timestamp(timestamp) => {
+                emit(&timestamp.value.to_be_bytes());
+                emit(&timestamp.echo_reply.to_be_bytes());
             }
-            let ws = data[2];
-            Some(TcpOption::WindowScale(ws))
-        }),
-    );
-
-    // SackPermitted parser
-    parsers.insert(4, Box::new(|_: &[u8]| Some(TcpOption::SackPermitted)));
-
-    // Sack parser
-    parsers.insert(
-        5,
-        Box::new(|data: &[u8]| {
-            if data.len() < 2 || data.len() % 8 != 2 { // Must be at least 2 bytes and x-2 % 8 == 0
-                return None;
+            TcpOption::QuickStartResponse(response) => emit(&response.to_be_bytes()[2..]),
+            TcpOption::UserTimeout(timeout) => emit(&timeout.to_be_bytes()),
+            TcpOption::TCPFastOpenCookie(cookie) => emit(&cookie.to_be_bytes()),
+            TcpOption::AlternateChecksumData(data)
+            | TcpOption::Skeeter(data)
+            | TcpOption::Bubba(data)
+            | TcpOption::SCPSCapabilities(data)
+            | TcpOption::SelectiveNegativeAcknowledgements(data)
+            | TcpOption::SNAP(data)
+            | TcpOption::TCPCompressionFilter(data)
+            | TcpOption::TCPAuthenticationOption(data)
+            | TcpOption::MultipathTCP(data)
+            | TcpOption::EncryptionNegotiation(data)
+            | TcpOption::AccECNOrder0(data)
+            | TcpOption::AccECNOrder1(data)
+            | TcpOption::RFC3692Experiment1(data)
+            | TcpOption::RFC3692Experiment2(data)
+            | TcpOption::Unknown { data, .. } => emit(data),
+            _ => {}
+        }
+    }
+}
+
+// `{}` renders the option on one line. `{:#}` puts each SACK block and each
+// timestamp field on its own indented line.
+impl fmt::Display for TcpOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpOption::Sack(sacks) if f.alternate() => {
+                write!(f, "SACK")?;
+                for sack in sacks {
+                    write!(f, "\n  left {} right {}", sack.left_edge, sack.right_edge)?;
+                }
+                Ok(())
             }
-            let mut sacks = Vec::new();
-            for i in (2..data.len()).step_by(8) {
-                if i + 8 > data.len() {
-                    break; // Exit if we cannot fill the right edge
+            TcpOption::Timestamp(timestamp) if f.alternate() => {
+                write!(f, "TS\n  val {}\n  ecr {}", timestamp.value, timestamp.echo_reply)
+            }
+            TcpOption::EndOfOptionList => write!(f, "EOL"),
+            TcpOption::NoOperation => write!(f, "NOP"),
+            TcpOption::MaximumSegmentSize(mss) => write!(f, "MSS {}", mss),
+            TcpOption::WindowScale(shift) => write!(f, "WS {}", shift.raw()),
+            TcpOption::SackPermitted => write!(f, "SACK permitted"),
+            TcpOption::Sack(sacks) => {
+                write!(f, "SACK")?;
+                for sack in sacks {
+                    write!(f, " {}", sack)?;
                 }
-                let left_edge = {
-                    let mut left_edge_bytes = [0u8; 4];
-                    left_edge_bytes.copy_from_slice(&data[i..i + 4]);
-                    u32::from_be_bytes(left_edge_bytes)
-                };
-                let right_edge = {
-                    let mut right_edge_bytes = [0u8; 4];
-                    right_edge_bytes.copy_from_slice(&data[i + 4..i + 8]);
-                    u32::from_be_bytes(right_edge_bytes)
-                };
-                sacks.push(Sack { left_edge, right_edge });
+                Ok(())
+            }
+            TcpOption::Timestamp(timestamp) => {
+                write!(f, "TS val {} ecr {}", timestamp.value, timestamp.echo_reply)
             }
-            Some(TcpOption::Sack(sacks))
-        }),
-    );
-
-    // Timestamp parser
-    parsers.insert(
-        8,
-        Box::new(|data: &[u8]| {
-            if data.len() != 10 {
-                return None;
+            TcpOption::PartialOrderServiceProfile(value)
+            | TcpOption::AlternateChecksumRequest(value)
+            | TcpOption::TrailerChecksum(value) => write!(f, "{} {}", self.name(), value),
+            TcpOption::Echo(value)
+            | TcpOption::EchoReply(value)
+            | TcpOption::ConnectionCount(value)
+            | TcpOption::ConnectionCountNew(value)
+            | TcpOption::ConnectionCountEcho(value) => write!(f, "{} {}", self.name(), value),
+            TcpOption::MD5Signature(digest) => write!(f, "{} {:032x}", self.name(), digest),
+            TcpOption::QuickStartResponse(response) => write!(f, "{} {:012x}", self.name(), response),
+            TcpOption::UserTimeout(timeout) => write!(f, "{} {}", self.name(), timeout),
+            TcpOption::TCPFastOpenCookie(cookie) => write!(f, "{} {:032x}", self.name(), cookie),
+            TcpOption::AlternateChecksumData(data)
+            | TcpOption::Skeeter(data)
+            | TcpOption::Bubba(data)
+            | TcpOption::SCPSCapabilities(data)
+            | TcpOption::SelectiveNegativeAcknowledgements(data)
+            | TcpOption::SNAP(data)
+            | TcpOption::TCPCompressionFilter(data)
+            | TcpOption::TCPAuthenticationOption(data)
+            | TcpOption::MultipathTCP(data)
+            | TcpOption::EncryptionNegotiation(data)
+            | TcpOption::AccECNOrder0(data)
+            | TcpOption::AccECNOrder1(data)
+            | TcpOption::RFC3692Experiment1(data)
+            | TcpOption::RFC3692Experiment2(data) => {
+                write!(f, "{}", self.name())?;
+                write_hex_payload(f, data)
             }
-            let tsval = {
-                let mut tsval_bytes = [0u8; 4];
-                tsval_bytes.copy_from_slice(&data[2..6]);
-                u32::from_be_bytes(tsval_bytes)
-            };
-            let tsecr = {
-                let mut tsecr_bytes = [0u8; 4];
-                tsecr_bytes.copy_from_slice(&data[6..10]);
-                u32::from_be_bytes(tsecr_bytes)
-            };
-            Some(TcpOption::Timestamp(Timestamp { value: tsval, echo_reply: tsecr }))
-        }),
-    );
-
-    // Skeeter parser
-    parsers.insert(16, Box::new(|_: &[u8]| Some(TcpOption::Skeeter)));
-
-    // Bubba parser
-    parsers.insert(17, Box::new(|_: &[u8]| Some(TcpOption::Bubba)));
-
-    // TrailerChecksum parser
-    parsers.insert(
-        18,
-        Box::new(|data: &[u8]| {
-            if data.len() != 3 {
-                return None;
+            TcpOption::TruncatedKind(kind) => write!(f, "Truncated kind {}", kind),
+            TcpOption::Unknown { kind, length, data } => {
+                write!(f, "Unknown kind {}", kind)?;
+                if let Some(length) = length {
+                    write!(f, " length {}", length)?;
+                }
+                write_hex_payload(f, data)
+            }
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+// Writes a payload as a space and its bytes in hex, or nothing when empty.
+fn write_hex_payload(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    if !data.is_empty() {
+        write!(f, " ")?;
+    }
+    for byte in data {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+/// An owned list of options, in the order they appear in the field.
+#[derive(Debug,Clone,Default)]
+pub struct TcpOptions(Vec<TcpOption>);
+
+impl TcpOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn as_slice(&self) -> &[TcpOption] {
+        &self.0
+    }
+
+    pub fn into_vec(self) -> Vec<TcpOption> {
+        self.0
+    }
+
+    /// Every option other than EOL and NOP padding has a kind in
+    /// `allowed_kinds`.
+    pub fn is_subset_of(&self, allowed_kinds: &[u8]) -> bool {
+        self.0
+            .iter()
+            .filter(|option| option.category() != OptionCategory::Padding)
+            .all(|option| allowed_kinds.contains(&option.kind()))
+    }
+}
+
+impl From<Vec<TcpOption>> for TcpOptions {
+    fn from(options: Vec<TcpOption>) -> Self {
+        Self(options)
+    }
+}
+
+impl FromIterator<TcpOption> for TcpOptions {
+    fn from_iter<I: IntoIterator<Item = TcpOption>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Extend<TcpOption> for TcpOptions {
+    fn extend<I: IntoIterator<Item = TcpOption>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+// `{}` separates the options with commas. `{:#}` puts each option on its own
+// line in its alternate form.
+impl fmt::Display for TcpOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, option) in self.0.iter().enumerate() {
+            if f.alternate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(f, "{:#}", option)?;
+            } else {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", option)?;
             }
-            let checksum = data[2];
-            Some(TcpOption::TrailerChecksum(checksum))
-        }),
-    );
+        }
+        Ok(())
+    }
+}
+
+/// Collects options in order and serializes them into a padded field.
+#[derive(Debug,Clone,Default)]
+pub struct OptionsBuilder {
+    options: Vec<TcpOption>,
+    strict: bool,
+}
 
-    // SCPSCapabilities parser
-    parsers.insert(20, Box::new(|_: &[u8]| Some(TcpOption::SCPSCapabilities)));
+impl OptionsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    // SelectiveNegativeAcknowledgements parser
-    parsers.insert(21, Box::new(|_: &[u8]| Some(TcpOption::SelectiveNegativeAcknowledgements)));
+    pub fn option(&mut self, option: TcpOption) -> &mut Self {
+        self.options.push(option);
+        self
+    }
 
-    // RecordBoundaries parser
-    parsers.insert(22, Box::new(|_: &[u8]| Some(TcpOption::RecordBoundaries)));
+    /// Adds an MSS derived from the MTU: the MTU less the 20-byte TCP header
+    /// and the 20-byte IPv4 or 40-byte IPv6 header.
+    pub fn mss_from_mtu(&mut self, mtu: u16, ipv6: bool) -> &mut Self {
+        let ip_header = if ipv6 { 40 } else { 20 };
+        self.option(TcpOption::MaximumSegmentSize(mtu.saturating_sub(20 + ip_header)))
+    }
 
-    // CorruptionExperienced parser
-    parsers.insert(23, Box::new(|_: &[u8]| Some(TcpOption::CorruptionExperienced)));
+    pub fn options(&self) -> &[TcpOption] {
+        &self.options
+    }
 
-    // SNAP parser
-    parsers.insert(24, Box::new(|_: &[u8]| Some(TcpOption::SNAP)));
+    /// In strict mode `build` rejects a field that does not fit the header,
+    /// that holds an option failing `TcpOption::is_valid`, or that `validate`
+    /// warns about. The default is lenient: the options are
+    /// encoded as given, so deliberately malformed fields can be crafted.
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
 
-    // TCPCompressionFilter parser
-    parsers.insert(26, Box::new(|_: &[u8]| Some(TcpOption::TCPCompressionFilter)));
+    pub fn build(&self) -> Result<Vec<u8>, ParseError> {
+        if !self.strict {
+            let mut bytes = Vec::with_capacity(padded_len(&self.options));
+            for option in &self.options {
+                option.write_to(&mut bytes);
+            }
+            bytes.resize(padded_len(&self.options), 0);
+            return Ok(bytes);
+        }
+        let bytes = serialize_options(&self.options)?;
+        if let Some(option) = self.options.iter().find(|option| !option.is_valid()) {
+            return Err(ParseError::InvalidOption(option.kind()));
+        }
+        match validate(&bytes).into_iter().next() {
+            Some(warning) => Err(ParseError::Validation(warning)),
+            None => Ok(bytes),
+        }
+    }
+}
 
-    // QuickStartResponse parser
-    parsers.insert(
-        27,
-        Box::new(|data: &[u8]| {
-            if data.len() != 8 {
-                return None;
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum ParseError {
+    /// The data offset is below the 5 words of the fixed TCP header.
+    InvalidDataOffset(u8),
+    /// The padded options length differs from the space the data offset leaves.
+    DataOffsetMismatch { expected: usize, actual: usize },
+    /// A SACK option carries more blocks than fit in the 40-byte field.
+    TooManySackBlocks(usize),
+    /// The padded options take more than the 40 bytes the header allows.
+    OptionsTooLong(usize),
+    /// A single option is longer than its length byte can express.
+    OptionTooLong { kind: u8, length: usize },
+    /// A hex stream has an odd number of digits.
+    OddHexLength(usize),
+    /// A hex stream has a non-hex character at this position.
+    InvalidHexDigit(usize),
+    /// The option's length does not fit its kind. The length counts the kind
+    /// and length bytes.
+    InvalidLength { kind: u8, length: usize },
+    /// An MPTCP option carries a different subtype than the one asked for.
+    UnexpectedMptcpSubtype(u8),
+    /// An MPTCP subtype failed to decode; the inner error says why.
+    MptcpSubtype(Box<ParseError>),
+    /// A strict build was given an option that fails `TcpOption::is_valid`.
+    InvalidOption(u8),
+    /// A strict build produced a field that `validate` warns about.
+    Validation(Diagnostic),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidDataOffset(words) => {
+                write!(f, "data offset of {} words is shorter than the TCP header", words)
             }
-            let cookie = {
-                let mut cookie_bytes = [0u8; 8];
-                cookie_bytes.copy_from_slice(&data[2..8]);
-                u64::from_be_bytes(cookie_bytes)
-            };
-            Some(TcpOption::QuickStartResponse(cookie))
-        }),
-    );
-
-    // UserTimeout parser
-    parsers.insert(
-        28,
-        Box::new(|data: &[u8]| {
-            if data.len() != 4 {
-                return None;
+            ParseError::DataOffsetMismatch { expected, actual } => write!(
+                f,
+                "data offset leaves {} bytes for options but they take {}",
+                expected, actual
+            ),
+            ParseError::TooManySackBlocks(blocks) => {
+                write!(f, "SACK option has {} blocks but at most 4 fit", blocks)
             }
-            let timeout = {
-                let mut timeout_bytes = [0u8; 2];
-                timeout_bytes.copy_from_slice(&data[2..4]);
-                u16::from_be_bytes(timeout_bytes)
-            };
-            Some(TcpOption::UserTimeout(timeout))
-        }),
-    );
-
-    // TCPAuthenticationOption parser
-    parsers.insert(29, Box::new(|_: &[u8]| Some(TcpOption::TCPAuthenticationOption)));
-
-    // MultipathTCP parser
-    parsers.insert(
-        30,
-        Box::new(|data: &[u8]| {
-            if data.len() < 4 {
-                return None;
+            ParseError::OptionsTooLong(len) => write!(f, "options take {} bytes but at most 40 fit", len),
+            ParseError::OptionTooLong { kind, length } => {
+                write!(f, "option kind {} takes {} bytes but its length byte holds at most 255", kind, length)
             }
-            let mut data_bytes = Vec::new();
-            data_bytes.extend_from_slice(&data[2..data.len()]);
-            Some(TcpOption::MultipathTCP(data_bytes))
-        }),
-    );
-
-    // TCPFastOpenCookie parser
-    parsers.insert(
-        34,
-        Box::new(|data: &[u8]| {
-            if data.len() != 18 {
-                return None;
+            ParseError::OddHexLength(len) => write!(f, "hex stream has an odd length of {}", len),
+            ParseError::InvalidHexDigit(pos) => write!(f, "invalid hex digit at position {}", pos),
+            ParseError::InvalidLength { kind, length } => {
+                write!(f, "option kind {} has invalid length {}", kind, length)
             }
-            let cookie = {
-                let mut cookie_bytes = [0u8; 16];
-                cookie_bytes.copy_from_slice(&data[2..18]);
-                u128::from_be_bytes(cookie_bytes)
-            };
-            Some(TcpOption::TCPFastOpenCookie(cookie))
-        }),
-    );
-
-    // EncryptionNegotiation parser
-    parsers.insert(
-        69,
-        Box::new(|data: &[u8]| {
-            if data.len() < 4 {
-                return None;
+            ParseError::UnexpectedMptcpSubtype(subtype) => write!(f, "unexpected MPTCP subtype {}", subtype),
+            ParseError::MptcpSubtype(_) => write!(f, "malformed MPTCP subtype option"),
+            ParseError::InvalidOption(kind) => write!(f, "option kind {} is not valid", kind),
+            ParseError::Validation(warning) => write!(f, "options field fails validation: {}", warning),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::MptcpSubtype(inner) => Some(inner.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Kinds that appear more than once with differing values, in order of first
+/// appearance. Repeated NOPs or identical copies are not flagged; two
+/// different values for the same kind usually means something rewrote or
+/// injected options.
+pub fn conflicting_duplicates(opts: &[TcpOption]) -> Vec<u8> {
+    let mut kinds = Vec::new();
+    for (i, a) in opts.iter().enumerate() {
+        let kind = a.kind();
+        if kinds.contains(&kind) {
+            continue;
+        }
+        if opts[i + 1..].iter().any(|b| b.kind() == kind && b != a) {
+            kinds.push(kind);
+        }
+    }
+    kinds
+}
+
+/// Authentication and encryption options, for auditing which connections are
+/// protected.
+pub fn security_options(opts: &[TcpOption]) -> Vec<&TcpOption> {
+    opts.iter()
+        .filter(|option| option.category() == OptionCategory::Security)
+        .collect()
+}
+
+/// Splits options into those only meaningful during the handshake and those
+/// that can appear on any segment, keeping field order within each.
+pub fn partition_by_phase(opts: Vec<TcpOption>) -> (Vec<TcpOption>, Vec<TcpOption>) {
+    opts.into_iter().partition(TcpOption::is_syn_only)
+}
+
+/// Groups options by kind, keeping every occurrence in field order.
+pub fn index_by_kind(opts: Vec<TcpOption>) -> BTreeMap<u8, Vec<TcpOption>> {
+    let mut index: BTreeMap<u8, Vec<TcpOption>> = BTreeMap::new();
+    for option in opts {
+        index.entry(option.kind()).or_default().push(option);
+    }
+    index
+}
+
+/// Features a single packet's options advertise, regardless of what the peer
+/// replies.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub struct AdvertisedFeatures {
+    pub sack_permitted: bool,
+    pub window_scaling: bool,
+    pub timestamps: bool,
+    pub mptcp: bool,
+    pub tfo: bool,
+    /// Accurate ECN, signalled by either AccECN kind or by an AccECN ExID in
+    /// an experimental kind.
+    pub ecn: bool,
+}
+
+pub fn advertised_features(opts: &[TcpOption]) -> AdvertisedFeatures {
+    let mut features = AdvertisedFeatures::default();
+    for option in opts {
+        match option {
+            TcpOption::SackPermitted => features.sack_permitted = true,
+            TcpOption::WindowScale(_) => features.window_scaling = true,
+            TcpOption::Timestamp(_) => features.timestamps = true,
+            TcpOption::MultipathTCP(_) => features.mptcp = true,
+            option if option.fast_open_cookie().is_some() => features.tfo = true,
+            option if accecn::AccEcn::from_option(option).is_some() => features.ecn = true,
+            _ => {}
+        }
+    }
+    features
+}
+
+/// The set of kinds present in a list of options, one bit per kind, for
+/// repeated membership tests over the same options.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,Hash)]
+pub struct KindSet([u64; 4]);
+
+impl KindSet {
+    pub fn insert(&mut self, kind: u8) {
+        self.0[(kind / 64) as usize] |= 1 << (kind % 64);
+    }
+
+    pub fn contains(&self, kind: u8) -> bool {
+        self.0[(kind / 64) as usize] & (1 << (kind % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    /// Kinds in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|&kind| self.contains(kind))
+    }
+}
+
+pub fn kinds_present(opts: &[TcpOption]) -> KindSet {
+    let mut kinds = KindSet::default();
+    for option in opts {
+        kinds.insert(option.kind());
+    }
+    kinds
+}
+
+/// Bytes the options take before padding, to see how much of the 40-byte
+/// budget is left.
+pub fn unpadded_length(opts: &[TcpOption]) -> usize {
+    opts.iter().map(TcpOption::encoded_len).sum()
+}
+
+// Length of the options field once padded to a multiple of 4 bytes.
+fn padded_len(opts: &[TcpOption]) -> usize {
+    unpadded_length(opts).div_ceil(4) * 4
+}
+
+/// Encodes the options in order and zero-pads the result to a multiple of 4
+/// bytes, as required for the data offset. Fails if an option does not fit
+/// its length byte or the field does not fit the header.
+pub fn serialize_options(opts: &[TcpOption]) -> Result<Vec<u8>, ParseError> {
+    let mut bytes = Vec::new();
+    serialize_into(opts, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Appends the padded options field to `buf`, reserving the whole field up
+/// front so no per-option buffers are allocated.
+pub fn serialize_into(opts: &[TcpOption], buf: &mut Vec<u8>) -> Result<(), ParseError> {
+    let len = check_lengths(opts)?;
+    let start = buf.len();
+    buf.reserve(len);
+    for option in opts {
+        option.write_to(buf);
+    }
+    buf.resize(start + len, 0);
+    Ok(())
+}
+
+/// Writes the padded options field into a stack array sized for the largest
+/// possible field, returning it with the number of bytes used.
+pub fn serialize_into_array(opts: &[TcpOption]) -> Result<([u8; 40], usize), ParseError> {
+    let len = check_lengths(opts)?;
+    let mut bytes = [0u8; 40];
+    let mut pos = 0;
+    for option in opts {
+        option.encode(|chunk| {
+            bytes[pos..pos + chunk.len()].copy_from_slice(chunk);
+            pos += chunk.len();
+        });
+    }
+    Ok((bytes, len))
+}
+
+/// Writes the options back exactly as they were received, for a list parsed
+/// with `ParseConfig::wire_faithful`. Other parses drop or repair options, so
+/// their output does not round-trip. Nothing is validated or reordered, and
+/// `Unknown` options keep their declared length, and a `TruncatedKind` is
+/// written as its lone kind byte. A trailing EOL is followed by
+/// zero padding to the next 4-byte boundary, which restores the field as long
+/// as it was padded the usual way; bytes after that are not recoverable.
+pub fn reserialize_faithful(opts: &[TcpOption]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(unpadded_length(opts) + 3);
+    for option in opts {
+        option.write_to(&mut bytes);
+    }
+    if let Some(TcpOption::EndOfOptionList) = opts.last() {
+        bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+    }
+    bytes
+}
+
+// Checks that every option fits its length byte and the padded field fits
+// the header, returning the padded length.
+fn check_lengths(opts: &[TcpOption]) -> Result<usize, ParseError> {
+    for option in opts {
+        if let TcpOption::Sack(sacks) = option {
+            if sacks.len() > 4 {
+                return Err(ParseError::TooManySackBlocks(sacks.len()));
             }
-            let mut data_bytes = Vec::new();
-            data_bytes.extend_from_slice(&data[2..data.len()]);
-            Some(TcpOption::EncryptionNegotiation(data_bytes))
-        }),
-    );
-
-    // AccECNOrder0 parser
-    parsers.insert(
-        172,
-        Box::new(|data: &[u8]| {
-            if data.len() < 4 {
-                return None;
+        }
+        let length = option.encoded_len();
+        if length > u8::MAX as usize {
+            return Err(ParseError::OptionTooLong { kind: option.kind(), length });
+        }
+    }
+    let len = padded_len(opts);
+    if len > 40 {
+        return Err(ParseError::OptionsTooLong(len));
+    }
+    Ok(len)
+}
+
+/// Checks that the options, once padded, exactly fill the space between the
+/// fixed 20-byte header and the data offset.
+pub fn validate_against_data_offset(opts: &[TcpOption], data_offset_words: u8) -> Result<(), ParseError> {
+    if data_offset_words < 5 {
+        return Err(ParseError::InvalidDataOffset(data_offset_words));
+    }
+    let expected = data_offset_words as usize * 4 - 20;
+    let actual = padded_len(opts);
+    if expected != actual {
+        return Err(ParseError::DataOffsetMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+/// The advertised MSS, or the default a peer must assume when none is sent:
+/// 536 for IPv4 (RFC 9293) and 1220 for IPv6 (RFC 8200).
+pub fn mss_or_default(opts: &[TcpOption], is_ipv6: bool) -> u16 {
+    opts.iter()
+        .find_map(|option| match option {
+            TcpOption::MaximumSegmentSize(mss) => Some(*mss),
+            _ => None,
+        })
+        .unwrap_or(if is_ipv6 { 1220 } else { 536 })
+}
+
+/// Largest TCP payload that fits in a packet of the given MTU, after the
+/// 20-byte IPv4 header, the 20-byte TCP header and the padded options.
+pub fn max_payload(mtu: usize, opts: &[TcpOption]) -> usize {
+    mtu.saturating_sub(20 + 20 + padded_len(opts))
+}
+
+/// Builds the SACK option a receiver sends for its out-of-order ranges, given
+/// as `(left_edge, right_edge)` in the order they arrived. Overlapping and
+/// adjacent ranges are coalesced, and blocks are ordered most recent first
+/// as RFC 2018 asks. At most `max_blocks` blocks are kept, and never more
+/// than 4; pass 3 when the segment also carries a timestamp. None when there
+/// is no block to send, since a SACK option must carry at least one.
+pub fn build_sack(received: &[(u32, u32)], max_blocks: usize) -> Option<TcpOption> {
+    let mut blocks: Vec<Sack> = Vec::new();
+    for &(left_edge, right_edge) in received.iter().rev() {
+        let mut block = Sack { left_edge, right_edge };
+        let mut position = blocks.len();
+        let mut i = 0;
+        while i < blocks.len() {
+            let other = blocks[i];
+            if seq::seq_leq(other.left_edge, block.right_edge) && seq::seq_leq(block.left_edge, other.right_edge) {
+                if seq::seq_lt(other.left_edge, block.left_edge) {
+                    block.left_edge = other.left_edge;
+                }
+                if seq::seq_lt(block.right_edge, other.right_edge) {
+                    block.right_edge = other.right_edge;
+                }
+                blocks.remove(i);
+                position = position.min(i);
+            } else {
+                i += 1;
             }
-            let mut data_bytes = Vec::new();
-            data_bytes.extend_from_slice(&data[2..data.len()]);
-            Some(TcpOption::AccECNOrder0(data_bytes))
-        }),
-    );
-
-    // AccECNOrder1 parser
-    parsers.insert(
-        174,
-        Box::new(|data: &[u8]| {
-            if data.len() < 4 {
-                return None;
+        }
+        blocks.insert(position.min(blocks.len()), block);
+    }
+    blocks.truncate(max_blocks.min(4));
+    if blocks.is_empty() {
+        return None;
+    }
+    Some(TcpOption::Sack(blocks))
+}
+
+/// Sum of the 16-bit big-endian words of the padded options field, unfolded,
+/// for the caller to add into the TCP checksum. The zero padding adds
+/// nothing, so the options are summed as they are encoded.
+pub fn options_checksum_words(opts: &[TcpOption]) -> u32 {
+    let mut sum = 0u32;
+    let mut pos = 0usize;
+    for option in opts {
+        option.encode(|chunk| {
+            for &byte in chunk {
+                sum += if pos.is_multiple_of(2) { (byte as u32) << 8 } else { byte as u32 };
+                pos += 1;
             }
-            let mut data_bytes = Vec::new();
-            data_bytes.extend_from_slice(&data[2..data.len()]);
-            Some(TcpOption::AccECNOrder1(data_bytes))
-        }),
-    );
+        });
+    }
+    sum
+}
+
+/// ExID that TCP Fast Open used in the experimental option before RFC 7413
+/// assigned kind 34.
+pub const TFO_EXPERIMENT_ID: u16 = 0xF989;
+
+// The error for an option whose length does not fit its kind.
+fn invalid_length(data: &[u8]) -> ParseError {
+    ParseError::InvalidLength { kind: data[0], length: data.len() }
+}
+
+// Options whose only payload is a 4-byte value.
+fn parse_u32(data: &[u8]) -> Result<u32, ParseError> {
+    if data.len() != 6 {
+        return Err(invalid_length(data));
+    }
+    let mut value_bytes = [0u8; 4];
+    value_bytes.copy_from_slice(&data[2..6]);
+    Ok(u32::from_be_bytes(value_bytes))
+}
+
+// Options with a variable-length payload of at least 2 bytes.
+fn parse_payload(data: &[u8], option: fn(Vec<u8>) -> TcpOption) -> Result<TcpOption, ParseError> {
+    if data.len() < 4 {
+        return Err(invalid_length(data));
+    }
+    Ok(option(data[2..].to_vec()))
+}
+
+// AccECN options carry up to three 3-byte counters, and may carry none.
+fn parse_accecn(data: &[u8], option: fn(Vec<u8>) -> TcpOption) -> Result<TcpOption, ParseError> {
+    if !(data.len() - 2).is_multiple_of(3) || data.len() > 11 {
+        return Err(invalid_length(data));
+    }
+    Ok(option(data[2..].to_vec()))
+}
+
+// Experimental options start with a 16-bit ExID (RFC 6994). They are kept
+// raw, so they re-encode under the kind they arrived with; a pre-RFC 7413
+// Fast Open cookie is read through `TcpOption::fast_open_cookie`.
+fn parse_experiment(data: &[u8], experiment: fn(Vec<u8>) -> TcpOption) -> Result<TcpOption, ParseError> {
+    if data.len() < 4 {
+        return Err(invalid_length(data));
+    }
+    Ok(experiment(data[2..].to_vec()))
+}
+
+fn parse_mss(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() != 4 {
+        return Err(invalid_length(data));
+    }
+    Ok(TcpOption::MaximumSegmentSize(u16::from_be_bytes([data[2], data[3]])))
+}
+
+fn parse_window_scale(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() != 3 {
+        return Err(invalid_length(data));
+    }
+    // RFC 7323 says to treat a shift above 14 as 14
+    Ok(TcpOption::WindowScale(WindowShift(data[2].min(WindowShift::MAX))))
+}
+
+// The payload is a whole number of 8-byte blocks.
+fn parse_sack(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() % 8 != 2 {
+        return Err(invalid_length(data));
+    }
+    let sacks = data[2..]
+        .chunks_exact(8)
+        .map(|block| Sack {
+            left_edge: u32::from_be_bytes([block[0], block[1], block[2], block[3]]),
+            right_edge: u32::from_be_bytes([block[4], block[5], block[6], block[7]]),
+        })
+        .collect();
+    Ok(TcpOption::Sack(sacks))
+}
+
+// The option is exactly 10 bytes, framing included; a 9- or 11-byte
+// timestamp is malformed rather than truncated or padded.
+fn parse_timestamp(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() != 10 {
+        return Err(invalid_length(data));
+    }
+    let value = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+    let echo_reply = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
+    Ok(TcpOption::Timestamp(Timestamp { value, echo_reply }))
+}
+
+// Options that are just the kind and length bytes.
+fn parse_empty(data: &[u8], option: TcpOption) -> Result<TcpOption, ParseError> {
+    if data.len() != 2 {
+        return Err(invalid_length(data));
+    }
+    Ok(option)
+}
+
+// Options whose payload this crate does not interpret, of any length.
+fn parse_opaque(data: &[u8], option: fn(Vec<u8>) -> TcpOption) -> Result<TcpOption, ParseError> {
+    Ok(option(data[2..].to_vec()))
+}
+
+// Options whose only payload is a single byte.
+fn parse_u8(data: &[u8], option: fn(u8) -> TcpOption) -> Result<TcpOption, ParseError> {
+    if data.len() != 3 {
+        return Err(invalid_length(data));
+    }
+    Ok(option(data[2]))
+}
+
+fn parse_alternate_checksum_data(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() < 3 {
+        return Err(invalid_length(data));
+    }
+    Ok(TcpOption::AlternateChecksumData(data[2..].to_vec()))
+}
 
+// Options whose only payload is a 16-byte value.
+fn parse_u128(data: &[u8], option: fn(u128) -> TcpOption) -> Result<TcpOption, ParseError> {
+    if data.len() != 18 {
+        return Err(invalid_length(data));
+    }
+    let mut value_bytes = [0u8; 16];
+    value_bytes.copy_from_slice(&data[2..18]);
+    Ok(option(u128::from_be_bytes(value_bytes)))
+}
+
+// The 48-bit payload is stored zero-extended.
+fn parse_quick_start(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() != 8 {
+        return Err(invalid_length(data));
+    }
+    let mut value_bytes = [0u8; 8];
+    value_bytes[2..].copy_from_slice(&data[2..8]);
+    Ok(TcpOption::QuickStartResponse(u64::from_be_bytes(value_bytes)))
+}
+
+fn parse_user_timeout(data: &[u8]) -> Result<TcpOption, ParseError> {
+    if data.len() != 4 {
+        return Err(invalid_length(data));
+    }
+    Ok(TcpOption::UserTimeout(u16::from_be_bytes([data[2], data[3]])))
+}
 
+type OptionParser = fn(&[u8]) -> Result<TcpOption, ParseError>;
 
+// Parser for each kind the crate types, sorted by kind. Each parser receives
+// the whole option, kind and length bytes included. This table is the single
+// source for dispatch and for `known_kinds`.
+const PARSERS: &[(u8, OptionParser)] = &[
+    (0, |_| Ok(TcpOption::EndOfOptionList)),
+    (1, |_| Ok(TcpOption::NoOperation)),
+    (2, parse_mss),
+    (3, parse_window_scale),
+    (4, |data| parse_empty(data, TcpOption::SackPermitted)),
+    (5, parse_sack),
+    (6, |data| parse_u32(data).map(TcpOption::Echo)),
+    (7, |data| parse_u32(data).map(TcpOption::EchoReply)),
+    (8, parse_timestamp),
+    (9, |data| parse_empty(data, TcpOption::PartialOrderConnectionPermitted)),
+    (10, |data| parse_u8(data, TcpOption::PartialOrderServiceProfile)),
+    (11, |data| parse_u32(data).map(TcpOption::ConnectionCount)),
+    (12, |data| parse_u32(data).map(TcpOption::ConnectionCountNew)),
+    (13, |data| parse_u32(data).map(TcpOption::ConnectionCountEcho)),
+    (14, |data| parse_u8(data, TcpOption::AlternateChecksumRequest)),
+    (15, parse_alternate_checksum_data),
+    (16, |data| parse_opaque(data, TcpOption::Skeeter)),
+    (17, |data| parse_opaque(data, TcpOption::Bubba)),
+    (18, |data| parse_u8(data, TcpOption::TrailerChecksum)),
+    (19, |data| parse_u128(data, TcpOption::MD5Signature)),
+    (20, |data| parse_opaque(data, TcpOption::SCPSCapabilities)),
+    (21, |data| parse_opaque(data, TcpOption::SelectiveNegativeAcknowledgements)),
+    (22, |data| parse_empty(data, TcpOption::RecordBoundaries)),
+    (23, |data| parse_empty(data, TcpOption::CorruptionExperienced)),
+    (24, |data| parse_opaque(data, TcpOption::SNAP)),
+    (26, |data| parse_opaque(data, TcpOption::TCPCompressionFilter)),
+    (27, parse_quick_start),
+    (28, parse_user_timeout),
+    (29, |data| parse_payload(data, TcpOption::TCPAuthenticationOption)),
+    (30, |data| parse_payload(data, TcpOption::MultipathTCP)),
+    (34, |data| parse_u128(data, TcpOption::TCPFastOpenCookie)),
+    (69, |data| parse_payload(data, TcpOption::EncryptionNegotiation)),
+    (172, |data| parse_accecn(data, TcpOption::AccECNOrder0)),
+    (174, |data| parse_accecn(data, TcpOption::AccECNOrder1)),
+    (253, |data| parse_experiment(data, TcpOption::RFC3692Experiment1)),
+    (254, |data| parse_experiment(data, TcpOption::RFC3692Experiment2)),
+];
 
-    parsers
-});
+// Fails the build if `PARSERS` is out of order or lists a kind twice, which
+// the binary search in `parser_for` relies on.
+const _: () = {
+    let mut i = 1;
+    while i < PARSERS.len() {
+        assert!(PARSERS[i - 1].0 < PARSERS[i].0, "PARSERS must be sorted by kind without duplicates");
+        i += 1;
+    }
+};
+
+fn parser_for(kind: u8) -> Option<OptionParser> {
+    PARSERS
+        .binary_search_by_key(&kind, |&(parser_kind, _)| parser_kind)
+        .ok()
+        .map(|index| PARSERS[index].1)
+}
+
+/// Kinds the crate decodes into a typed variant, in ascending order. Other
+/// kinds parse as `Unknown`.
+pub fn known_kinds() -> impl Iterator<Item = u8> {
+    PARSERS.iter().map(|&(kind, _)| kind)
+}
+
+// Kinds that are a lone kind byte with no length byte. Every other kind is
+// framed by a length byte counting the kind and length bytes themselves. Both
+// `Framer` and the encoder decide framing from this table alone.
+const SINGLE_BYTE_KINDS: &[u8] = &[0, 1];
+
+pub fn is_single_byte_kind(kind: u8) -> bool {
+    SINGLE_BYTE_KINDS.contains(&kind)
+}
+
+// Splits the options field into the raw bytes of each option, kind and
+// length bytes included. Stops after the end of option list, and yields the
+// offset of the first option whose framing runs past the field as an error.
+struct Framer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Framer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for Framer<'a> {
+    type Item = Result<(usize, &'a [u8]), usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let offset = self.pos;
+        let kind = self.data[offset];
+        if is_single_byte_kind(kind) {
+            self.pos = offset + 1;
+            // Nothing after an EOL is framed.
+            if kind == TcpOption::EndOfOptionList.kind() {
+                self.pos = self.data.len();
+            }
+            return Some(Ok((offset, &self.data[offset..offset + 1])));
+        }
+        let length = self.data.get(offset + 1).map_or(0, |&length| length as usize);
+        if length < 2 || offset + length > self.data.len() {
+            self.pos = self.data.len();
+            return Some(Err(offset));
+        }
+        self.pos = offset + length;
+        Some(Ok((offset, &self.data[offset..offset + length])))
+    }
+}
+
+/// Splits the first option in `data` into its kind and payload without
+/// typing it. Single-byte kinds have an empty payload. Returns None when the
+/// option's framing runs past the end of `data`.
+pub fn raw_kind_bytes(data: &[u8]) -> Option<(u8, &[u8])> {
+    let (_, option) = Framer::new(data).next()?.ok()?;
+    Some((option[0], option.get(2..).unwrap_or(&[])))
+}
+
+/// Calls `f` with the kind and payload of each option in the field, without
+/// typing or allocating anything. Fails once an option's framing runs past the
+/// field, after `f` has seen every option before it.
+pub fn for_each_option<F: FnMut(u8, &[u8])>(data: &[u8], mut f: F) -> Result<(), ParseError> {
+    for step in Framer::new(data) {
+        let (_, option) = step.map_err(|offset| truncated(data, offset))?;
+        f(option[0], option.get(2..).unwrap_or(&[]));
+    }
+    Ok(())
+}
+
+/// Parses only the option at index `n`, counting NOPs and an EOL, and steps
+/// over the ones before it by their length. None when the field has fewer
+/// options; an error when the framing breaks before or at the option.
+pub fn nth_option(data: &[u8], n: usize) -> Option<Result<TcpOption, ParseError>> {
+    for (index, step) in Framer::new(data).enumerate() {
+        let option = match step {
+            Ok((_, option)) => option,
+            Err(offset) => return Some(Err(truncated(data, offset))),
+        };
+        if index == n {
+            return Some(parse_framed(option));
+        }
+    }
+    None
+}
+
+// Parses one framed option, keeping kinds without a parser as `Unknown`.
+fn parse_framed(option: &[u8]) -> Result<TcpOption, ParseError> {
+    match parser_for(option[0]) {
+        Some(parser) => parser(option),
+        None => Ok(TcpOption::Unknown { kind: option[0], length: None, data: option.get(2..).unwrap_or(&[]).to_vec() }),
+    }
+}
+
+fn parse_option(option: &[u8]) -> Option<TcpOption> {
+    parse_framed(option).ok()
+}
+
+// The error for the option at `offset` whose framing runs past the field.
+// A missing length byte is reported as a length of 0.
+fn truncated(data: &[u8], offset: usize) -> ParseError {
+    let length = data.get(offset + 1).map_or(0, |&length| length as usize);
+    ParseError::InvalidLength { kind: data[offset], length }
+}
+
+// Keeps what remains of the option at `offset` whose framing runs past the
+// field, with the length byte it declared, so the field can still be written
+// back byte for byte.
+fn salvage_truncated(data: &[u8], offset: usize) -> TcpOption {
+    match data.get(offset + 1) {
+        Some(&length) => TcpOption::Unknown { kind: data[offset], length: Some(length), data: data[offset + 2..].to_vec() },
+        None => TcpOption::TruncatedKind(data[offset]),
+    }
+}
+
+/// Iterates over the options in a field, yielding an error for each option
+/// that fails to parse and carrying on with the next one. An option whose
+/// framing runs past the field ends the iteration after its error, since
+/// nothing after it can be located.
+pub struct OptionsIter<'a> {
+    framer: Framer<'a>,
+    strict: bool,
+    failed: bool,
+}
+
+impl<'a> OptionsIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { framer: Framer::new(data), strict: false, failed: false }
+    }
+
+    /// Stops after the first error instead of carrying on.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+}
+
+impl<'a> Iterator for OptionsIter<'a> {
+    type Item = Result<TcpOption, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let item = match self.framer.next()? {
+            Ok((_, option)) => parse_framed(option),
+            Err(offset) => Err(truncated(self.framer.data, offset)),
+        };
+        self.failed = self.strict && item.is_err();
+        Some(item)
+    }
+}
+
+// Parses every option the framer yields, skipping options that fail to
+// parse, and stopping at the first framing error.
+fn walk(data: &[u8]) -> impl Iterator<Item = TcpOption> + '_ {
+    Framer::new(data)
+        .map_while(Result::ok)
+        .filter_map(|(_, option)| parse_option(option))
+}
+
+pub fn parse_options(data: &[u8]) -> Vec<TcpOption> {
+    parse_options_into(data)
+}
+
+/// Parses into any collection that can be extended with options, such as a
+/// `TcpOptions` or an inline small vector.
+pub fn parse_options_into<C: Extend<TcpOption> + Default>(data: &[u8]) -> C {
+    let mut options = C::default();
+    options.extend(walk(data));
+    options
+}
+
+/// Parses into a fixed array of 16 slots instead of a `Vec`, returning the
+/// number of slots filled. Options past the 16th are dropped. Variable-length
+/// options still own their payloads.
+pub fn parse_options_array(data: &[u8]) -> ([Option<TcpOption>; 16], usize) {
+    let mut options: [Option<TcpOption>; 16] = Default::default();
+    let mut count = 0;
+    for (slot, option) in options.iter_mut().zip(walk(data)) {
+        *slot = Some(option);
+        count += 1;
+    }
+    (options, count)
+}
+
+/// How `parse_with_config` treats the parts of a field that are not plain,
+/// well-formed options. The default matches `parse_options`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct ParseConfig {
+    /// Keep NOPs in the output rather than dropping them.
+    pub keep_nops: bool,
+    /// Keep kinds without a parser as `Unknown` rather than dropping them.
+    pub keep_unknown: bool,
+    /// Treat a window scale above 14 as 14, as RFC 7323 says. Otherwise the
+    /// option is kept as an `Unknown` of kind 3 with the shift as received.
+    pub clamp_window_scale: bool,
+    /// Keep malformed and truncated options as `Unknown`, with their declared
+    /// length, rather than dropping them. A kind byte that ends the field
+    /// before its length byte is kept as `TruncatedKind`.
+    pub preserve_malformed: bool,
+    /// Fail on the first malformed or truncated option. Takes precedence over
+    /// `preserve_malformed`.
+    pub reject_malformed: bool,
+    /// Continue past errors under `reject_malformed`: step over an option that
+    /// fails to parse by its declared length and carry on instead of failing.
+    /// The errors are collected by `parse_with_config_errors`. Without
+    /// `reject_malformed` this changes nothing, since malformed options are
+    /// already stepped over, and a truncated option ends the walk either way.
+    pub resync: bool,
+}
+
+impl Default for ParseConfig {
+    fn default() -> Self {
+        Self {
+            keep_nops: true,
+            keep_unknown: true,
+            clamp_window_scale: true,
+            preserve_malformed: false,
+            reject_malformed: false,
+            resync: false,
+        }
+    }
+}
+
+impl ParseConfig {
+    /// Every option up to the end of option list is kept as received:
+    /// malformed and truncated options and out-of-range window scales become
+    /// `Unknown` with their original bytes, and a kind byte that ends the
+    /// field before its length byte becomes `TruncatedKind`. Writing the
+    /// result out with `reserialize_faithful` reproduces the field up to and
+    /// including its end of option list; bytes after that are not kept.
+    pub fn wire_faithful() -> Self {
+        Self {
+            keep_nops: true,
+            keep_unknown: true,
+            clamp_window_scale: false,
+            preserve_malformed: true,
+            reject_malformed: false,
+            resync: false,
+        }
+    }
+
+    /// Only the options that carry meaning, with values as a receiver would
+    /// apply them. Malformed fields are rejected.
+    pub fn normalized() -> Self {
+        Self {
+            keep_nops: false,
+            keep_unknown: true,
+            clamp_window_scale: true,
+            preserve_malformed: false,
+            reject_malformed: true,
+            resync: false,
+        }
+    }
+}
+
+/// Parses the field according to `config`. Fails only when the config rejects
+/// malformed options and does not resync past them.
+pub fn parse_with_config(data: &[u8], config: &ParseConfig) -> Result<Vec<TcpOption>, ParseError> {
+    let (options, errors) = parse_with_config_errors(data, config);
+    match errors.into_iter().next() {
+        Some(err) if config.reject_malformed && !config.resync => Err(err),
+        _ => Ok(options),
+    }
+}
+
+/// Parses the field according to `config`, returning the options together
+/// with an error for each option that failed to parse. Parsing stops at the
+/// first error when the config rejects malformed options without resyncing,
+/// and always at an option whose length runs past the field.
+pub fn parse_with_config_errors(data: &[u8], config: &ParseConfig) -> (Vec<TcpOption>, Vec<ParseError>) {
+    let stop_on_error = config.reject_malformed && !config.resync;
+    let mut options = Vec::new();
+    let mut errors = Vec::new();
+    for step in Framer::new(data) {
+        let option = match step {
+            Ok((_, option)) => option,
+            Err(offset) => {
+                errors.push(truncated(data, offset));
+                if !stop_on_error && config.preserve_malformed {
+                    options.push(salvage_truncated(data, offset));
+                }
+                break;
+            }
+        };
+        let kind = option[0];
+        let raw = || TcpOption::Unknown { kind, length: None, data: option[2..].to_vec() };
+        let parsed = match parser_for(kind) {
+            _ if kind == 1 && !config.keep_nops => continue,
+            None if !config.keep_unknown => continue,
+            Some(_) if kind == 3 && option.len() == 3 && option[2] > WindowShift::MAX && !config.clamp_window_scale => {
+                Ok(raw())
+            }
+            Some(parser) => parser(option),
+            None => Ok(raw()),
+        };
+        match parsed {
+            Ok(parsed) => options.push(parsed),
+            Err(err) => {
+                errors.push(err);
+                if stop_on_error {
+                    break;
+                }
+                if config.preserve_malformed {
+                    options.push(raw());
+                }
+            }
+        }
+    }
+    (options, errors)
+}
+
+/// How serious a diagnostic is, ordered from least to most.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum Severity {
+    /// Worth knowing, but the field is well-formed.
+    Info,
+    /// The field is legal or was repaired, but is unusual or deviates from
+    /// what the RFCs ask senders to do.
+    Warning,
+    /// Part of the field could not be decoded.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What a diagnostic is about, for tools that filter or count diagnostics
+/// without matching on their message.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum DiagnosticCode {
+    /// An option's framing runs past the end of the field.
+    Truncated,
+    /// An option's length does not fit its kind.
+    Malformed,
+    /// A kind this crate has no parser for.
+    UnknownKind,
+    /// A window scale above 14 was clamped to 14.
+    ClampedWindowScale,
+    /// A SACK with a partial block was kept with its whole blocks only.
+    SalvagedSack,
+    /// A non-zero byte follows the end of option list.
+    DataAfterEol,
+    /// A SYN carries no MSS option.
+    MissingMssInSyn,
+}
+
+/// Something the lenient parser had to skip, repair or reinterpret, or that
+/// validation found wrong with a field. `kind` is the option concerned, and
+/// `offset` its position in the field where there is one.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub kind: u8,
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, code: DiagnosticCode, kind: u8, offset: Option<usize>, message: String) -> Self {
+        Self { severity, code, kind, offset, message }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Parses leniently like `parse_options`, but reports each deviation from a
+/// well-formed field instead of silently absorbing it.
+pub fn parse_with_diagnostics(data: &[u8]) -> (Vec<TcpOption>, Vec<Diagnostic>) {
+    let mut options = Vec::new();
+    let mut diagnostics = Vec::new();
+    for step in Framer::new(data) {
+        let (offset, option) = match step {
+            Ok(step) => step,
+            Err(offset) => {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    DiagnosticCode::Truncated,
+                    data[offset],
+                    Some(offset),
+                    format!("option at offset {} runs past the end of the field", offset),
+                ));
+                options.push(salvage_truncated(data, offset));
+                break;
+            }
+        };
+        let kind = option[0];
+        if parser_for(kind).is_none() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Info,
+                DiagnosticCode::UnknownKind,
+                kind,
+                Some(offset),
+                format!("unknown option kind {} at offset {}", kind, offset),
+            ));
+        }
+        if kind == 3 && option.len() == 3 && option[2] > WindowShift::MAX {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::ClampedWindowScale,
+                kind,
+                Some(offset),
+                format!("window scale {} at offset {} clamped to 14", option[2], offset),
+            ));
+        }
+        let parsed = if kind == 5 && option.len() % 8 != 2 {
+            let blocks = (option.len() - 2) / 8;
+            if blocks == 0 {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    DiagnosticCode::Malformed,
+                    kind,
+                    Some(offset),
+                    format!("SACK at offset {} has no whole block", offset),
+                ));
+                continue;
+            }
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::SalvagedSack,
+                kind,
+                Some(offset),
+                format!("SACK at offset {} has a partial block, kept {} whole blocks", offset, blocks),
+            ));
+            let mut salvaged = vec![5, (2 + blocks * 8) as u8];
+            salvaged.extend_from_slice(&option[2..2 + blocks * 8]);
+            parse_option(&salvaged)
+        } else {
+            parse_option(option)
+        };
+        match parsed {
+            Some(parsed) => options.push(parsed),
+            None => diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                DiagnosticCode::Malformed,
+                kind,
+                Some(offset),
+                format!("malformed option kind {} at offset {}", kind, offset),
+            )),
+        }
+    }
+    (options, diagnostics)
+}
+
+/// Parses a hex stream as copied from Wireshark ("Copy as Hex Stream"): two
+/// hex digits per byte with no separators. A non-hex character is reported
+/// by its byte position before the length is checked.
+pub fn parse_hex_stream(s: &str) -> Result<Vec<TcpOption>, ParseError> {
+    if let Some((pos, _)) = s.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidHexDigit(pos));
+    }
+    let digits = s.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return Err(ParseError::OddHexLength(digits.len()));
+    }
+    let mut data = Vec::with_capacity(digits.len() / 2);
+    for pos in (0..digits.len()).step_by(2) {
+        data.push(hex_digit(digits, pos)? << 4 | hex_digit(digits, pos + 1)?);
+    }
+    Ok(parse_options(&data))
+}
+
+fn hex_digit(digits: &[u8], pos: usize) -> Result<u8, ParseError> {
+    (digits[pos] as char)
+        .to_digit(16)
+        .map(|digit| digit as u8)
+        .ok_or(ParseError::InvalidHexDigit(pos))
+}
+
+/// Checks a raw options field for problems the parser tolerates.
+pub fn validate(data: &[u8]) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    let eol = Framer::new(data).find_map(|step| match step {
+        Ok((offset, option)) if option[0] == 0 => Some(offset),
+        _ => None,
+    });
+    if let Some(eol) = eol {
+        // Only zero padding belongs after the end of option list.
+        if let Some(pos) = data[eol + 1..].iter().position(|&byte| byte != 0) {
+            let offset = eol + 1 + pos;
+            warnings.push(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::DataAfterEol,
+                0,
+                Some(offset),
+                format!("non-zero byte at offset {} after end of option list", offset),
+            ));
+        }
+    }
+    warnings
+}
+
+/// Checks the options field of a SYN, which on top of `validate` is expected
+/// to carry an MSS. A SYN without one is legal, and the peer then assumes 536
+/// bytes (1220 over IPv6), but real stacks always send one.
+pub fn validate_syn(data: &[u8]) -> Vec<Diagnostic> {
+    let mut warnings = validate(data);
+    if !walk(data).any(|option| matches!(option, TcpOption::MaximumSegmentSize(_))) {
+        warnings.push(Diagnostic::new(
+            Severity::Warning,
+            DiagnosticCode::MissingMssInSyn,
+            2,
+            None,
+            "SYN carries no MSS option".to_string(),
+        ));
+    }
+    warnings
+}
+
+/// Parses the field and renders one line per option, followed by a line per
+/// diagnostic, for printing from a command-line tool. What remains of a
+/// truncated option is reported by its diagnostic only.
+pub fn parse_and_pretty(data: &[u8]) -> String {
+    let (mut options, diagnostics) = parse_with_diagnostics(data);
+    // `parse_with_diagnostics` keeps a truncated option as the last one.
+    if diagnostics.iter().any(|diagnostic| diagnostic.code == DiagnosticCode::Truncated) {
+        options.pop();
+    }
+    let mut lines: Vec<String> = options.iter().map(TcpOption::to_string).collect();
+    for diagnostic in &diagnostics {
+        lines.push(format!("{}: {}", diagnostic.severity, diagnostic));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Kinds IANA has assigned to an option, from the TCP Option Kind Numbers
+    // registry. Reserved, unassigned and released kinds are left out.
+    const IANA_ASSIGNED_KINDS: &[u8] = &[
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 26, 27, 28, 29,
+        30, 34, 69, 172, 174, 253, 254,
+    ];
+
+    // One option of every variant, covering every kind in `PARSERS`.
+    fn one_of_each_variant() -> Vec<TcpOption> {
+        vec![
+            TcpOption::EndOfOptionList,
+            TcpOption::NoOperation,
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::WindowScale(WindowShift(7)),
+            TcpOption::SackPermitted,
+            TcpOption::Sack(vec![Sack::new(1, 2), Sack::new(3, 4)]),
+            TcpOption::Echo(1),
+            TcpOption::EchoReply(2),
+            TcpOption::Timestamp(Timestamp::new(1, 2)),
+            TcpOption::PartialOrderConnectionPermitted,
+            TcpOption::PartialOrderServiceProfile(0x80),
+            TcpOption::ConnectionCount(1),
+            TcpOption::ConnectionCountNew(2),
+            TcpOption::ConnectionCountEcho(3),
+            TcpOption::AlternateChecksumRequest(1),
+            TcpOption::AlternateChecksumData(vec![1, 2]),
+            TcpOption::Skeeter(vec![1]),
+            TcpOption::Bubba(vec![]),
+            TcpOption::TrailerChecksum(1),
+            TcpOption::MD5Signature(0x0102),
+            TcpOption::SCPSCapabilities(vec![1, 2]),
+            TcpOption::SelectiveNegativeAcknowledgements(vec![0, 0, 0, 1, 0, 2]),
+            TcpOption::RecordBoundaries,
+            TcpOption::CorruptionExperienced,
+            TcpOption::SNAP(vec![7]),
+            TcpOption::TCPCompressionFilter(vec![3, 4]),
+            TcpOption::QuickStartResponse(0x0102_0304_0506),
+            TcpOption::UserTimeout(0x8001),
+            TcpOption::TCPAuthenticationOption(vec![1, 2, 3, 4]),
+            TcpOption::MultipathTCP(vec![0x00, 0x81]),
+            TcpOption::TCPFastOpenCookie(0x0102),
+            TcpOption::EncryptionNegotiation(vec![1, 2]),
+            TcpOption::AccECNOrder0(vec![0, 0, 1]),
+            TcpOption::AccECNOrder1(vec![0, 0, 1]),
+            TcpOption::RFC3692Experiment1(vec![0x12, 0x34]),
+            TcpOption::RFC3692Experiment2(vec![0x12, 0x34, 5]),
+            TcpOption::Unknown { kind: 99, length: None, data: vec![1, 2, 3] },
+        ]
+    }
+
+    #[test]
+    fn test_vectors_parse_to_their_expected_options() {
+        use test_vectors::*;
+        let vectors = [
+            (LINUX_SYN, linux_syn_expected()),
+            (WINDOWS_SYN, windows_syn_expected()),
+            (MACOS_SYN, macos_syn_expected()),
+            (MPTCP_MP_CAPABLE_SYN, mptcp_mp_capable_syn_expected()),
+            (SACK_DATA_SEGMENT, sack_data_segment_expected()),
+        ];
+        for (bytes, expected) in vectors {
+            assert_eq!(parse_options(bytes), expected);
+            assert_eq!(serialize_options(&expected).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn nop_is_framed_as_a_single_byte() {
+        assert_eq!(
+            parse_options(&[1, 2, 4, 5, 0xb4]),
+            vec![TcpOption::NoOperation, TcpOption::MaximumSegmentSize(1460)]
+        );
+    }
+
+    #[test]
+    fn timestamp_requires_exactly_ten_bytes() {
+        let mut field = vec![8, 10, 0, 0, 0, 1, 0, 0, 0, 2];
+        assert_eq!(
+            parse_timestamp(&field),
+            Ok(TcpOption::Timestamp(Timestamp { value: 1, echo_reply: 2 }))
+        );
+        field[1] = 9;
+        assert_eq!(parse_timestamp(&field[..9]), Err(ParseError::InvalidLength { kind: 8, length: 9 }));
+        field[1] = 11;
+        field.push(3);
+        assert_eq!(parse_timestamp(&field), Err(ParseError::InvalidLength { kind: 8, length: 11 }));
+        assert_eq!(parse_options(&field), vec![]);
+    }
+
+    #[test]
+    fn clones_of_variable_length_options_are_independent() {
+        let mut sack = TcpOption::Sack(vec![Sack::new(30, 40), Sack::new(10, 20)]);
+        let sack_clone = sack.clone();
+        sack.sort_sack_blocks();
+        assert_eq!(sack_clone.sack_blocks(), &[Sack::new(30, 40), Sack::new(10, 20)]);
+        assert_ne!(sack, sack_clone);
+
+        let mut mptcp = TcpOption::MultipathTCP(vec![0x00, 0x81]);
+        let mptcp_clone = mptcp.clone();
+        if let TcpOption::MultipathTCP(payload) = &mut mptcp {
+            payload[1] = 0x01;
+        }
+        assert_eq!(mptcp_clone, TcpOption::MultipathTCP(vec![0x00, 0x81]));
+    }
+
+    #[test]
+    fn length_byte_counts_the_whole_option() {
+        let options = one_of_each_variant();
+        let kinds = kinds_present(&options);
+        assert!(known_kinds().all(|kind| kinds.contains(kind)));
+        for option in &options {
+            let bytes = option.to_bytes();
+            assert_eq!(bytes.len(), option.encoded_len(), "{:?}", option);
+            if is_single_byte_kind(option.kind()) {
+                assert_eq!(bytes, [option.kind()]);
+            } else {
+                assert_eq!(bytes[1] as usize, bytes.len(), "{:?}", option);
+            }
+        }
+    }
+
+    #[test]
+    fn every_variant_survives_a_round_trip() {
+        for option in one_of_each_variant() {
+            assert_eq!(parse_framed(&option.to_bytes()), Ok(option.clone()), "{:?}", option);
+        }
+    }
+
+    #[test]
+    fn options_without_a_payload_require_a_length_of_two() {
+        for kind in [4, 9, 22, 23] {
+            assert_eq!(parse_framed(&[kind, 3, 0]), Err(ParseError::InvalidLength { kind, length: 3 }));
+        }
+        assert_eq!(
+            parse_options(&[29, 6, 1, 2, 0xaa, 0xbb]),
+            vec![TcpOption::TCPAuthenticationOption(vec![1, 2, 0xaa, 0xbb])]
+        );
+    }
+
+    #[test]
+    fn experimental_fast_open_keeps_its_kind() {
+        for cookie in [vec![], vec![0xaa; 8], vec![0xbb; 16]] {
+            let mut field = vec![254, 4 + cookie.len() as u8, 0xf9, 0x89];
+            field.extend_from_slice(&cookie);
+            field.resize(field.len().next_multiple_of(4), 0);
+            let options = parse_options(&field);
+            assert_eq!(options[0].kind(), 254);
+            assert_eq!(options[0].fast_open_cookie(), Some(cookie));
+            assert!(advertised_features(&options).tfo);
+            assert_eq!(serialize_options(&options).unwrap(), field);
+        }
+        assert_eq!(TcpOption::TCPFastOpenCookie(1).fast_open_cookie().unwrap().len(), 16);
+        assert_eq!(TcpOption::RFC3692Experiment2(vec![0x12, 0x34]).fast_open_cookie(), None);
+    }
+
+    #[test]
+    fn serialization_rejects_options_that_do_not_fit() {
+        let long = TcpOption::RFC3692Experiment2(vec![0; 300]);
+        let error = Err(ParseError::OptionTooLong { kind: 254, length: 302 });
+        assert_eq!(serialize_options(std::slice::from_ref(&long)), error);
+        assert_eq!(serialize_into(std::slice::from_ref(&long), &mut Vec::new()), error.map(drop));
+        let wide = [TcpOption::MD5Signature(1), TcpOption::TCPFastOpenCookie(2), TcpOption::Timestamp(Timestamp::new(1, 2))];
+        assert_eq!(serialize_options(&wide), Err(ParseError::OptionsTooLong(48)));
+        assert_eq!(OptionsBuilder::new().option(long).build().unwrap().len(), 304);
+    }
+
+    #[test]
+    fn kind_two_is_length_prefixed() {
+        assert!(!is_single_byte_kind(2));
+        let framed: Vec<_> = Framer::new(&[2, 4, 5, 0xb4, 1, 0]).collect();
+        assert_eq!(framed, [Ok((0, &[2, 4, 5, 0xb4][..])), Ok((4, &[1][..])), Ok((5, &[0][..]))]);
+        assert_eq!(TcpOption::MaximumSegmentSize(1460).to_bytes(), [2, 4, 5, 0xb4]);
+    }
+
+    #[test]
+    fn diagnostics_carry_a_code_and_severity() {
+        let (_, diagnostics) = parse_with_diagnostics(&[3, 3, 15, 8, 10, 0]);
+        let codes: Vec<_> = diagnostics.iter().map(|diagnostic| (diagnostic.severity, diagnostic.code)).collect();
+        assert_eq!(
+            codes,
+            [(Severity::Warning, DiagnosticCode::ClampedWindowScale), (Severity::Error, DiagnosticCode::Truncated)]
+        );
+        let warnings = validate_syn(&[0, 1]);
+        let codes: Vec<_> = warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, [DiagnosticCode::DataAfterEol, DiagnosticCode::MissingMssInSyn]);
+    }
+
+    #[test]
+    fn sack_without_a_whole_block_is_dropped() {
+        let (options, diagnostics) = parse_with_diagnostics(&[5, 6, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(options, [TcpOption::NoOperation, TcpOption::NoOperation]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].severity, diagnostics[0].code), (Severity::Error, DiagnosticCode::Malformed));
+
+        let (options, diagnostics) = parse_with_diagnostics(&[5, 12, 0, 0, 0, 1, 0, 0, 0, 2, 9, 9]);
+        assert_eq!(options, [TcpOption::Sack(vec![Sack::new(1, 2)])]);
+        assert_eq!(diagnostics[0].code, DiagnosticCode::SalvagedSack);
+    }
+
+    #[test]
+    fn build_sack_caps_and_orders_blocks() {
+        let received = [(100, 200), (300, 400), (500, 600), (700, 800), (900, 1000), (350, 450)];
+        assert_eq!(
+            build_sack(&received, 3),
+            Some(TcpOption::Sack(vec![Sack::new(300, 450), Sack::new(900, 1000), Sack::new(700, 800)]))
+        );
+        assert_eq!(build_sack(&received, 10).unwrap().sack_blocks().len(), 4);
+        assert_eq!(build_sack(&received, 0), None);
+        assert_eq!(build_sack(&[], 4), None);
+    }
+
+    #[test]
+    fn wire_faithful_keeps_what_other_presets_repair() {
+        let field = [1, 2, 3, 5, 3, 3, 15, 29, 4, 1, 2, 99, 2, 0, 7];
+        let options = parse_with_config(&field, &ParseConfig::wire_faithful()).unwrap();
+        assert_eq!(
+            options,
+            [
+                TcpOption::NoOperation,
+                TcpOption::Unknown { kind: 2, length: None, data: vec![5] },
+                TcpOption::Unknown { kind: 3, length: None, data: vec![15] },
+                TcpOption::TCPAuthenticationOption(vec![1, 2]),
+                TcpOption::Unknown { kind: 99, length: None, data: vec![] },
+                TcpOption::EndOfOptionList,
+            ]
+        );
+        assert!(parse_with_config(&field, &ParseConfig::normalized()).is_err());
+        assert_eq!(
+            parse_with_config(&[1, 1, 3, 3, 15, 2, 4, 5, 0xb4], &ParseConfig::normalized()),
+            Ok(vec![TcpOption::WindowScale(WindowShift(14)), TcpOption::MaximumSegmentSize(1460)])
+        );
+    }
+
+    #[test]
+    fn wire_faithful_parses_reserialize_byte_for_byte() {
+        let fields: [&[u8]; 7] = [
+            &[1, 1, 2, 3, 5, 3, 3, 15, 0, 0, 0, 0],
+            &[29, 16, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 1, 1, 1, 0],
+            &[254, 20, 0xf9, 0x89, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+            &[254, 12, 0xf9, 0x89, 1, 2, 3, 4, 5, 6, 7, 8],
+            &[1, 8, 10, 0, 0, 0, 1],
+            &[1, 2],
+            &[1, 1, 1, 8],
+        ];
+        for field in fields {
+            let options = parse_with_config(field, &ParseConfig::wire_faithful()).unwrap();
+            assert_eq!(reserialize_faithful(&options), field, "{:?}", options);
+        }
+        let (options, _) = parse_with_diagnostics(&[1, 1, 1, 8]);
+        assert_eq!(options.last(), Some(&TcpOption::TruncatedKind(8)));
+        assert_eq!(reserialize_faithful(&options), [1, 1, 1, 8]);
+    }
+
+    #[test]
+    fn conflicting_duplicates_ignore_repeated_nops() {
+        let options = [
+            TcpOption::NoOperation,
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::NoOperation,
+            TcpOption::MaximumSegmentSize(1400),
+        ];
+        assert_eq!(conflicting_duplicates(&options), [2]);
+        assert_eq!(TcpOption::Unknown { kind: 99, length: None, data: vec![] }.kind(), 99);
+    }
+
+    #[test]
+    fn into_bytes_reuses_the_payload_buffer() {
+        let mut data = Vec::with_capacity(16);
+        data.extend_from_slice(&[1, 2, 3]);
+        let ptr = data.as_ptr();
+        let bytes = TcpOption::Unknown { kind: 99, length: None, data }.into_bytes();
+        assert_eq!(bytes, [99, 5, 1, 2, 3]);
+        assert_eq!(bytes.as_ptr(), ptr);
+
+        let nop = TcpOption::Unknown { kind: 1, length: None, data: vec![1, 2] };
+        assert_eq!(nop.clone().into_bytes(), nop.to_bytes());
+    }
+
+    #[test]
+    fn resync_recovers_options_after_a_malformed_one() {
+        let field = [2, 4, 5, 0xb4, 8, 3, 0, 4, 2, 1, 0, 0];
+        let rejecting = ParseConfig { reject_malformed: true, ..ParseConfig::default() };
+        assert_eq!(
+            parse_with_config(&field, &rejecting),
+            Err(ParseError::InvalidLength { kind: 8, length: 3 })
+        );
+        let resyncing = ParseConfig { resync: true, ..rejecting };
+        let (options, errors) = parse_with_config_errors(&field, &resyncing);
+        assert_eq!(
+            options,
+            [
+                TcpOption::MaximumSegmentSize(1460),
+                TcpOption::SackPermitted,
+                TcpOption::NoOperation,
+                TcpOption::EndOfOptionList,
+            ]
+        );
+        assert_eq!(errors, [ParseError::InvalidLength { kind: 8, length: 3 }]);
+        assert_eq!(parse_with_config(&field, &resyncing), Ok(options));
+    }
+
+    #[test]
+    fn every_iana_assigned_kind_has_a_parser() {
+        for &kind in IANA_ASSIGNED_KINDS {
+            assert!(parser_for(kind).is_some(), "kind {} has no parser", kind);
+        }
+    }
+
+    #[test]
+    fn strict_build_rejects_invalid_options() {
+        let blocks: Vec<Sack> = (0..5).map(|i| Sack::new(i * 10, i * 10 + 5)).collect();
+        let mut builder = OptionsBuilder::new();
+        builder.option(TcpOption::Sack(blocks));
+        assert_eq!(builder.build().unwrap().len(), 44);
+        assert_eq!(builder.strict(true).build(), Err(ParseError::TooManySackBlocks(5)));
+
+        for option in [
+            TcpOption::MaximumSegmentSize(0),
+            TcpOption::Sack(vec![]),
+            TcpOption::Sack(vec![Sack::new(20, 10)]),
+        ] {
+            let kind = option.kind();
+            let mut builder = OptionsBuilder::new();
+            builder.strict(true).option(option);
+            assert_eq!(builder.build(), Err(ParseError::InvalidOption(kind)));
+        }
+    }
+
+    #[test]
+    fn pretty_report_has_a_line_per_option_and_diagnostic() {
+        assert_eq!(
+            parse_and_pretty(test_vectors::LINUX_SYN),
+            "MSS 1460\nSACK permitted\nTS val 10103660 ecr 0\nNOP\nWS 7"
+        );
+        assert_eq!(parse_and_pretty(&[0xff, 0xff, 0x00]), "error: option at offset 0 runs past the end of the field");
+        assert_eq!(TcpOption::Unknown { kind: 99, length: None, data: vec![] }.to_string(), "Unknown kind 99");
+        assert_eq!(TcpOption::Bubba(vec![]).to_string(), "Bubba");
+    }
+
+    #[test]
+    fn hex_stream_parses_to_options() {
+        assert_eq!(
+            parse_hex_stream("020405b40402080a"),
+            Ok(vec![TcpOption::MaximumSegmentSize(1460), TcpOption::SackPermitted])
+        );
+        assert_eq!(parse_hex_stream("02040"), Err(ParseError::OddHexLength(5)));
+        assert_eq!(parse_hex_stream("é0"), Err(ParseError::InvalidHexDigit(0)));
+        assert_eq!(parse_hex_stream("02x4"), Err(ParseError::InvalidHexDigit(2)));
+    }
+
+    #[test]
+    fn clamped_and_unknown_options_give_two_diagnostics() {
+        let (options, diagnostics) = parse_with_diagnostics(&[3, 3, 20, 99, 2]);
+        assert_eq!(options.len(), 2);
+        let codes: Vec<_> = diagnostics.iter().map(|diagnostic| (diagnostic.severity, diagnostic.code)).collect();
+        assert_eq!(
+            codes,
+            [(Severity::Warning, DiagnosticCode::ClampedWindowScale), (Severity::Info, DiagnosticCode::UnknownKind)]
+        );
+    }
+
+    #[test]
+    fn sack_len_spans_the_wrap() {
+        let sack = Sack::new(0xffff_fff0, 0x10);
+        assert_eq!(sack.len(), 0x20);
+        assert!(!sack.is_empty());
+        assert!(Sack::new(7, 7).is_empty());
+    }
+
+    #[test]
+    fn sort_sack_blocks_orders_by_left_edge_across_the_wrap() {
+        let mut sack = TcpOption::Sack(vec![Sack::new(300, 400), Sack::new(100, 200), Sack::new(200, 250)]);
+        sack.sort_sack_blocks();
+        assert_eq!(sack.sack_blocks(), &[Sack::new(100, 200), Sack::new(200, 250), Sack::new(300, 400)]);
+
+        let mut wrapped = TcpOption::Sack(vec![
+            Sack::new(0x10, 0x20),
+            Sack::new(0xffff_ff00, 0xffff_ff80),
+            Sack::new(0xffff_fff0, 0x8),
+        ]);
+        wrapped.sort_sack_blocks();
+        assert_eq!(
+            wrapped.sack_blocks(),
+            &[Sack::new(0xffff_ff00, 0xffff_ff80), Sack::new(0xffff_fff0, 0x8), Sack::new(0x10, 0x20)]
+        );
+    }
+
+    #[test]
+    fn checksum_words_of_a_linux_syn() {
+        assert_eq!(options_checksum_words(&test_vectors::linux_syn_expected()), 0x43d4);
+    }
+
+    #[test]
+    fn nth_option_parses_only_the_option_asked_for() {
+        assert_eq!(
+            nth_option(test_vectors::LINUX_SYN, 2),
+            Some(Ok(TcpOption::Timestamp(Timestamp::new(0x009a2b6c, 0))))
+        );
+        assert_eq!(nth_option(test_vectors::LINUX_SYN, 5), None);
+        assert_eq!(nth_option(&[1, 8, 10, 0], 1), Some(Err(ParseError::InvalidLength { kind: 8, length: 10 })));
+    }
+
+    #[test]
+    fn strict_options_iter_stops_after_the_first_error() {
+        let field = [2, 4, 5, 0xb4, 4, 3, 0, 1];
+        let error = ParseError::InvalidLength { kind: 4, length: 3 };
+        let lenient: Vec<_> = OptionsIter::new(&field).collect();
+        assert_eq!(lenient, [Ok(TcpOption::MaximumSegmentSize(1460)), Err(error.clone()), Ok(TcpOption::NoOperation)]);
+        let strict: Vec<_> = OptionsIter::new(&field).strict().collect();
+        assert_eq!(strict, [Ok(TcpOption::MaximumSegmentSize(1460)), Err(error)]);
+    }
+
+    #[test]
+    fn for_each_option_sees_every_kind_in_order() {
+        let mut kinds = Vec::new();
+        for_each_option(test_vectors::LINUX_SYN, |kind, _| kinds.push(kind)).unwrap();
+        assert_eq!(kinds, [2, 4, 8, 1, 3]);
+    }
+
+    #[test]
+    fn parse_options_into_fills_any_collection() {
+        let options: Vec<TcpOption> = parse_options_into(test_vectors::LINUX_SYN);
+        assert_eq!(options, test_vectors::linux_syn_expected());
+        let options: TcpOptions = parse_options_into(test_vectors::LINUX_SYN);
+        assert_eq!(options.into_vec(), test_vectors::linux_syn_expected());
+    }
+
+    #[test]
+    fn validate_warns_about_an_option_after_the_eol() {
+        let warnings = validate(&[0, 2, 4, 5, 0xb4]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].code, warnings[0].offset), (DiagnosticCode::DataAfterEol, Some(1)));
+        assert_eq!(validate(&[2, 4, 5, 0xb4, 0, 0, 0, 0]), []);
+    }
+
+    #[test]
+    fn serialization_rejects_more_than_four_sack_blocks() {
+        let sack = TcpOption::Sack((0..5).map(|i| Sack::new(i * 10, i * 10 + 5)).collect());
+        assert_eq!(serialize_options(&[sack]), Err(ParseError::TooManySackBlocks(5)));
+    }
+
+    #[test]
+    fn window_shift_is_capped_at_fourteen() {
+        assert_eq!(WindowShift::new(14).map(|shift| shift.raw()), Some(14));
+        assert_eq!(WindowShift::new(15), None);
+    }
+
+    #[test]
+    fn is_valid_checks_option_invariants() {
+        assert!(TcpOption::MaximumSegmentSize(1460).is_valid());
+        assert!(!TcpOption::MaximumSegmentSize(0).is_valid());
+        assert!(TcpOption::Sack(vec![Sack::new(10, 20)]).is_valid());
+        assert!(!TcpOption::Sack((0..5).map(|i| Sack::new(i * 10, i * 10 + 5)).collect()).is_valid());
+        assert!(!TcpOption::Sack(vec![Sack::new(20, 10)]).is_valid());
+        assert!(!TcpOption::Sack(vec![Sack::new(10, 10)]).is_valid());
+        assert!(!TcpOption::Sack(vec![]).is_valid());
+        assert!(TcpOption::TCPAuthenticationOption(vec![1, 2]).is_valid());
+        assert!(!TcpOption::TCPAuthenticationOption(vec![]).is_valid());
+        assert!(!TcpOption::MultipathTCP(vec![]).is_valid());
+    }
+
+    #[test]
+    fn syn_with_an_mss_is_not_flagged() {
+        let warnings = validate_syn(test_vectors::LINUX_SYN);
+        assert!(warnings.iter().all(|warning| warning.code != DiagnosticCode::MissingMssInSyn));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn timestamp_now_does_not_go_backwards() {
+        let TcpOption::Timestamp(first) = TcpOption::timestamp_now(0) else {
+            panic!("timestamp_now did not return a timestamp");
+        };
+        let TcpOption::Timestamp(second) = TcpOption::timestamp_now(7) else {
+            panic!("timestamp_now did not return a timestamp");
+        };
+        assert!(second.value() >= first.value());
+        assert_eq!(second.echo_reply(), 7);
+    }
+
+    #[test]
+    fn data_offset_must_match_the_padded_options() {
+        let options = test_vectors::linux_syn_expected();
+        assert_eq!(validate_against_data_offset(&options, 10), Ok(()));
+        assert_eq!(
+            validate_against_data_offset(&options, 8),
+            Err(ParseError::DataOffsetMismatch { expected: 12, actual: 20 })
+        );
+        assert_eq!(validate_against_data_offset(&options, 4), Err(ParseError::InvalidDataOffset(4)));
+    }
+
+    #[test]
+    fn parse_options_array_fills_a_slot_per_option() {
+        let (slots, count) = parse_options_array(test_vectors::LINUX_SYN);
+        assert_eq!(count, 5);
+        assert!(slots[..5].iter().all(Option::is_some));
+        assert!(slots[5..].iter().all(Option::is_none));
+        assert_eq!(slots[0], Some(TcpOption::MaximumSegmentSize(1460)));
+    }
+
+    #[test]
+    fn max_payload_leaves_room_for_headers_and_options() {
+        assert_eq!(max_payload(1500, &test_vectors::linux_syn_expected()), 1440);
+        assert_eq!(max_payload(1500, &[]), 1460);
+    }
+
+    #[test]
+    fn tcp_options_can_be_extended() {
+        let mut options = TcpOptions::from(vec![TcpOption::MaximumSegmentSize(1460)]);
+        options.extend([TcpOption::NoOperation, TcpOption::SackPermitted]);
+        assert_eq!(
+            options.as_slice(),
+            [TcpOption::MaximumSegmentSize(1460), TcpOption::NoOperation, TcpOption::SackPermitted]
+        );
+    }
+
+    #[test]
+    fn sack_block_displays_its_edges() {
+        assert_eq!(Sack::new(1000, 2000).to_string(), "1000:2000");
+    }
+
+    #[test]
+    fn sacks_iter_yields_the_blocks_of_a_sack_only() {
+        let sack = TcpOption::Sack(vec![Sack::new(1, 2), Sack::new(3, 4)]);
+        assert_eq!(sack.sacks_iter().count(), 2);
+        assert_eq!(TcpOption::MaximumSegmentSize(1460).sacks_iter().count(), 0);
+    }
+
+    #[test]
+    fn mss_falls_back_to_the_protocol_default() {
+        let options = [TcpOption::NoOperation, TcpOption::MaximumSegmentSize(1400)];
+        assert_eq!(mss_or_default(&options, false), 1400);
+        assert_eq!(mss_or_default(&options, true), 1400);
+        assert_eq!(mss_or_default(&[TcpOption::NoOperation], false), 536);
+        assert_eq!(mss_or_default(&[TcpOption::NoOperation], true), 1220);
+    }
+
+    #[test]
+    fn index_by_kind_keeps_every_occurrence() {
+        let index = index_by_kind(test_vectors::windows_syn_expected());
+        assert_eq!(index[&1], [TcpOption::NoOperation, TcpOption::NoOperation, TcpOption::NoOperation]);
+        assert_eq!(index[&2], [TcpOption::MaximumSegmentSize(1460)]);
+        assert_eq!(index.keys().copied().collect::<Vec<_>>(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn size_class_buckets_by_encoded_length() {
+        assert_eq!(TcpOption::NoOperation.size_class(), SizeClass::Single);
+        assert_eq!(TcpOption::MaximumSegmentSize(1460).size_class(), SizeClass::Small);
+        assert_eq!(TcpOption::Timestamp(Timestamp::new(1, 2)).size_class(), SizeClass::Medium);
+        assert_eq!(TcpOption::MultipathTCP(vec![0x00, 0x81, 1, 2, 3, 4, 5, 6, 7, 8]).size_class(), SizeClass::Large);
+    }
+
+    #[test]
+    fn security_options_finds_tcp_ao() {
+        let options = [
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::TCPAuthenticationOption(vec![1, 2, 3, 4]),
+            TcpOption::NoOperation,
+        ];
+        assert_eq!(security_options(&options), [&options[1]]);
+        assert!(security_options(&test_vectors::linux_syn_expected()).is_empty());
+    }
+
+    #[test]
+    fn partition_by_phase_separates_handshake_options() {
+        let sack = TcpOption::Sack(vec![Sack::new(1, 2)]);
+        let (syn_only, any) = partition_by_phase(vec![TcpOption::MaximumSegmentSize(1460), sack.clone()]);
+        assert_eq!(syn_only, [TcpOption::MaximumSegmentSize(1460)]);
+        assert_eq!(any, [sack]);
+    }
+
+    #[test]
+    fn mss_from_mtu_subtracts_the_headers() {
+        assert_eq!(OptionsBuilder::new().mss_from_mtu(1500, false).options(), [TcpOption::MaximumSegmentSize(1460)]);
+        assert_eq!(OptionsBuilder::new().mss_from_mtu(1500, true).options(), [TcpOption::MaximumSegmentSize(1440)]);
+    }
+
+    #[test]
+    fn reordered_sacks_compare_equal_unordered() {
+        let a = TcpOption::Sack(vec![Sack::new(1, 2), Sack::new(3, 4)]);
+        let b = TcpOption::Sack(vec![Sack::new(3, 4), Sack::new(1, 2)]);
+        assert_ne!(a, b);
+        assert!(a.sack_eq_unordered(&b));
+        assert!(!a.sack_eq_unordered(&TcpOption::Sack(vec![Sack::new(1, 2)])));
+    }
+
+    #[test]
+    fn unpadded_length_counts_bytes_before_padding() {
+        let options = [
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::Timestamp(Timestamp::new(1, 2)),
+            TcpOption::NoOperation,
+            TcpOption::WindowScale(WindowShift(7)),
+        ];
+        assert_eq!(unpadded_length(&options), 18);
+        assert_eq!(serialize_options(&options).unwrap().len(), 20);
+    }
+
+    #[test]
+    fn serialize_into_array_writes_a_syn() {
+        let (bytes, len) = serialize_into_array(&test_vectors::linux_syn_expected()).unwrap();
+        assert_eq!(len, 20);
+        assert_eq!(&bytes[..len], test_vectors::LINUX_SYN);
+        assert!(bytes[len..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn is_behavioral_separates_protocol_affecting_options() {
+        assert!(TcpOption::MaximumSegmentSize(1460).is_behavioral());
+        assert!(TcpOption::Timestamp(Timestamp::new(1, 2)).is_behavioral());
+        assert!(!TcpOption::NoOperation.is_behavioral());
+        assert!(!TcpOption::RecordBoundaries.is_behavioral());
+        assert!(!TcpOption::Echo(1).is_behavioral());
+    }
+
+    #[test]
+    fn raw_kind_bytes_splits_the_first_option() {
+        assert_eq!(raw_kind_bytes(test_vectors::LINUX_SYN), Some((2, &[0x05, 0xb4][..])));
+        assert_eq!(raw_kind_bytes(&[1, 2]), Some((1, &[][..])));
+        assert_eq!(raw_kind_bytes(&[2, 4, 5]), None);
+    }
+
+    #[test]
+    fn advertised_features_of_a_syn() {
+        assert_eq!(
+            advertised_features(&test_vectors::linux_syn_expected()),
+            AdvertisedFeatures { sack_permitted: true, window_scaling: true, timestamps: true, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn to_bytes_with_length_misframes_the_option() {
+        assert_eq!(TcpOption::MaximumSegmentSize(1460).to_bytes_with_length(6), [2, 6, 5, 0xb4]);
+        assert_eq!(TcpOption::NoOperation.to_bytes_with_length(4), [1, 4]);
+    }
+
+    #[test]
+    fn kinds_present_has_a_bit_per_kind() {
+        let kinds = kinds_present(&test_vectors::linux_syn_expected());
+        assert!(kinds.contains(8));
+        assert!(!kinds.contains(5));
+        assert_eq!(kinds.iter().collect::<Vec<_>>(), [1, 2, 3, 4, 8]);
+    }
+
+    #[test]
+    fn sack_renders_on_one_or_several_lines() {
+        let sack = TcpOption::Sack(vec![Sack::new(1000, 2000), Sack::new(3000, 4000)]);
+        assert_eq!(format!("{}", sack), "SACK 1000:2000 3000:4000");
+        assert_eq!(format!("{:#}", sack), "SACK\n  left 1000 right 2000\n  left 3000 right 4000");
+    }
+
+    #[test]
+    fn syn_is_checked_against_a_kind_allowlist() {
+        let syn = TcpOptions::from(test_vectors::linux_syn_expected());
+        assert!(syn.is_subset_of(&[2, 3, 4, 8]));
+        assert!(!syn.is_subset_of(&[2, 3, 4]));
+    }
+
+    #[test]
+    fn sack_blocks_are_a_slice() {
+        let options = parse_options(test_vectors::SACK_DATA_SEGMENT);
+        let blocks: &[Sack] = options[5].sack_blocks();
+        assert_eq!(blocks, [Sack::new(0x7a3b1c00, 0x7a3b21b4)]);
+        assert!(options[2].sack_blocks().is_empty());
+    }
+
+    #[test]
+    fn unknown_options_of_parsed_kinds_must_parse() {
+        let short_mss = TcpOption::Unknown { kind: 2, length: None, data: vec![5] };
+        let wide_shift = TcpOption::Unknown { kind: 3, length: None, data: vec![15] };
+        assert!(!short_mss.is_valid());
+        assert!(!wide_shift.is_valid());
+        assert!(TcpOption::Unknown { kind: 3, length: None, data: vec![14] }.is_valid());
+        assert!(TcpOption::Unknown { kind: 99, length: None, data: vec![1] }.is_valid());
+
+        let options = parse_with_config(&[2, 3, 5, 1, 3, 3, 15, 0], &ParseConfig::wire_faithful()).unwrap();
+        assert_eq!(options[..3], [short_mss, TcpOption::NoOperation, wide_shift]);
+        for option in [&options[0], &options[2]] {
+            let mut builder = OptionsBuilder::new();
+            builder.strict(true).option(option.clone());
+            assert_eq!(builder.build(), Err(ParseError::InvalidOption(option.kind())));
+        }
+    }
+}
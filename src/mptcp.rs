@@ -0,0 +1,256 @@
+//! Decoding of Multipath TCP (RFC 8684) subtypes carried in the MultipathTCP
+//! option. The option keeps its payload raw; these helpers interpret it.
+
+use crate::{ParseError, TcpOption};
+
+pub const MP_CAPABLE_SUBTYPE: u8 = 0;
+pub const DSS_SUBTYPE: u8 = 2;
+
+/// An MP_CAPABLE option. Version 0 (RFC 6824) sends the sender's key on the
+/// SYN; version 1 (RFC 8684) sends no key on the SYN, the sender's key on the
+/// SYN-ACK, and may carry a data-level length and checksum with the keys on
+/// the third ACK.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct MpCapable {
+    pub version: u8,
+    pub flags: u8,
+    pub sender_key: Option<u64>,
+    pub receiver_key: Option<u64>,
+    pub data_level_length: Option<u16>,
+    pub checksum: Option<u16>,
+}
+
+impl MpCapable {
+    /// Decodes a MultipathTCP payload, starting at the subtype byte.
+    pub fn parse(payload: &[u8]) -> Result<MpCapable, ParseError> {
+        let Some(&first) = payload.first() else {
+            return Err(invalid_length(payload));
+        };
+        if first >> 4 != MP_CAPABLE_SUBTYPE {
+            return Err(ParseError::UnexpectedMptcpSubtype(first >> 4));
+        }
+        MpCapable::decode(payload).ok_or_else(|| invalid_length(payload))
+    }
+
+    /// Decodes the option if it is a well-formed MPTCP MP_CAPABLE option.
+    pub fn from_option(option: &TcpOption) -> Option<MpCapable> {
+        match option {
+            TcpOption::MultipathTCP(payload) => MpCapable::parse(payload).ok(),
+            _ => None,
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Option<MpCapable> {
+        let version = payload[0] & 0x0f;
+        let valid_len = match version {
+            0 => matches!(payload.len(), 10 | 18),
+            _ => matches!(payload.len(), 2 | 10 | 18 | 20 | 22),
+        };
+        if !valid_len {
+            return None;
+        }
+        let mut rest = &payload[2..];
+        let sender_key = take_number(&mut rest, true);
+        let receiver_key = take_number(&mut rest, true);
+        let data_level_length = take(&mut rest, 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+        let checksum = take(&mut rest, 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+        Some(MpCapable { version, flags: payload[1], sender_key, receiver_key, data_level_length, checksum })
+    }
+}
+
+// Data Sequence Signal flags, in the second byte of the payload.
+const DSS_DATA_FIN: u8 = 0x10;
+const DSS_DSN_64: u8 = 0x08;
+const DSS_MAPPING: u8 = 0x04;
+const DSS_DATA_ACK_64: u8 = 0x02;
+const DSS_DATA_ACK: u8 = 0x01;
+
+/// A Data Sequence Signal option.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Dss {
+    pub data_fin: bool,
+    pub data_ack: Option<u64>,
+    pub mapping: Option<DssMapping>,
+}
+
+/// The data sequence mapping of a DSS option. A 4-byte DSN on the wire is
+/// stored zero-extended.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct DssMapping {
+    pub data_sequence_number: u64,
+    pub subflow_sequence_number: u32,
+    pub data_level_length: u16,
+    pub checksum: Option<u16>,
+}
+
+impl Dss {
+    /// Decodes a MultipathTCP payload, starting at the subtype byte. A payload
+    /// whose length does not match its flags fails with `MptcpSubtype`
+    /// wrapping the length error.
+    pub fn parse(payload: &[u8]) -> Result<Dss, ParseError> {
+        let Some(&first) = payload.first() else {
+            return Err(invalid_length(payload));
+        };
+        if first >> 4 != DSS_SUBTYPE {
+            return Err(ParseError::UnexpectedMptcpSubtype(first >> 4));
+        }
+        Dss::decode(payload).ok_or_else(|| invalid_length(payload))
+    }
+
+    /// Decodes the option if it is a well-formed MPTCP DSS option.
+    pub fn from_option(option: &TcpOption) -> Option<Dss> {
+        match option {
+            TcpOption::MultipathTCP(payload) => Dss::parse(payload).ok(),
+            _ => None,
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Option<Dss> {
+        if payload.len() < 2 {
+            return None;
+        }
+        let flags = payload[1];
+        let mut rest = &payload[2..];
+        let data_ack = if flags & DSS_DATA_ACK != 0 {
+            Some(take_number(&mut rest, flags & DSS_DATA_ACK_64 != 0)?)
+        } else {
+            None
+        };
+        let mapping = if flags & DSS_MAPPING != 0 {
+            let data_sequence_number = take_number(&mut rest, flags & DSS_DSN_64 != 0)?;
+            let subflow_sequence_number = take_number(&mut rest, false)? as u32;
+            let data_level_length = u16::from_be_bytes(take(&mut rest, 2)?.try_into().ok()?);
+            let checksum = match rest.len() {
+                0 => None,
+                2 => Some(u16::from_be_bytes(take(&mut rest, 2)?.try_into().ok()?)),
+                _ => return None,
+            };
+            Some(DssMapping { data_sequence_number, subflow_sequence_number, data_level_length, checksum })
+        } else {
+            None
+        };
+        if !rest.is_empty() {
+            return None;
+        }
+        Some(Dss { data_fin: flags & DSS_DATA_FIN != 0, data_ack, mapping })
+    }
+}
+
+fn invalid_length(payload: &[u8]) -> ParseError {
+    ParseError::MptcpSubtype(Box::new(ParseError::InvalidLength { kind: 30, length: payload.len() + 2 }))
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if rest.len() < len {
+        return None;
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Some(head)
+}
+
+fn take_number(rest: &mut &[u8], wide: bool) -> Option<u64> {
+    if wide {
+        Some(u64::from_be_bytes(take(rest, 8)?.try_into().ok()?))
+    } else {
+        Some(u32::from_be_bytes(take(rest, 4)?.try_into().ok()?) as u64)
+    }
+}
+
+/// Checks the DSS checksum against the mapped payload (RFC 8684 section
+/// 3.3.1). The checksum covers the payload and a pseudo-header of the 64-bit
+/// DSN, the subflow sequence number, the data-level length and two zero bytes.
+/// Returns false when the option carries no mapping or no checksum. A DSN sent
+/// in 4 bytes is used zero-extended, which matches the sender only while the
+/// upper 32 bits of the data sequence space are zero.
+pub fn validate_dss_checksum(dss: &Dss, payload: &[u8]) -> bool {
+    let Some(DssMapping { data_sequence_number, subflow_sequence_number, data_level_length, checksum: Some(checksum) }) = dss.mapping else {
+        return false;
+    };
+    let mut pseudo_header = [0u8; 16];
+    pseudo_header[..8].copy_from_slice(&data_sequence_number.to_be_bytes());
+    pseudo_header[8..12].copy_from_slice(&subflow_sequence_number.to_be_bytes());
+    pseudo_header[12..14].copy_from_slice(&data_level_length.to_be_bytes());
+    let mut sum = ones_complement_sum(0, &pseudo_header);
+    sum = ones_complement_sum(sum, payload);
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16) == checksum
+}
+
+fn ones_complement_sum(mut sum: u32, bytes: &[u8]) -> u32 {
+    let mut words = bytes.chunks_exact(2);
+    for word in &mut words {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    if let [last] = words.remainder() {
+        sum += (*last as u32) << 8;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // DSS with a 4-byte DSN of 1, subflow sequence number 1, a data-level
+    // length of 4 and the checksum computed by hand over the payload below.
+    const DSS_WITH_CHECKSUM: &[u8] = &[0x20, 0x04, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, 0xfb, 0xf3];
+    const MAPPED_PAYLOAD: &[u8] = &[1, 2, 3, 4];
+
+    #[test]
+    fn dss_checksum_is_checked_against_the_payload() {
+        let dss = Dss::parse(DSS_WITH_CHECKSUM).unwrap();
+        assert!(validate_dss_checksum(&dss, MAPPED_PAYLOAD));
+        assert!(!validate_dss_checksum(&dss, &[1, 2, 3, 5]));
+
+        let mut corrupted = DSS_WITH_CHECKSUM.to_vec();
+        corrupted[13] = 0xf4;
+        assert!(!validate_dss_checksum(&Dss::parse(&corrupted).unwrap(), MAPPED_PAYLOAD));
+        assert!(!validate_dss_checksum(&Dss::parse(&DSS_WITH_CHECKSUM[..12]).unwrap(), MAPPED_PAYLOAD));
+    }
+
+    // An MP_CAPABLE payload of the given version with `extra` after the flags.
+    fn mp_capable(version: u8, extra: &[u8]) -> Vec<u8> {
+        let mut payload = vec![MP_CAPABLE_SUBTYPE << 4 | version, 0x81];
+        payload.extend_from_slice(extra);
+        payload
+    }
+
+    #[test]
+    fn mp_capable_decodes_both_versions() {
+        let keys = [[0x11; 8], [0x22; 8]].concat();
+        let (sender, receiver) = (Some(0x1111_1111_1111_1111), Some(0x2222_2222_2222_2222));
+
+        let v0 = MpCapable::parse(&mp_capable(0, &keys[..8])).unwrap();
+        assert_eq!((v0.version, v0.flags, v0.sender_key, v0.receiver_key), (0, 0x81, sender, None));
+        let v0 = MpCapable::parse(&mp_capable(0, &keys)).unwrap();
+        assert_eq!((v0.sender_key, v0.receiver_key), (sender, receiver));
+        assert!(MpCapable::parse(&mp_capable(0, &[])).is_err());
+        assert!(MpCapable::parse(&mp_capable(0, &keys[..12])).is_err());
+
+        let v1 = MpCapable::parse(&mp_capable(1, &[])).unwrap();
+        assert_eq!((v1.version, v1.sender_key, v1.receiver_key), (1, None, None));
+        let v1 = MpCapable::parse(&mp_capable(1, &keys[..8])).unwrap();
+        assert_eq!((v1.sender_key, v1.receiver_key), (sender, None));
+        let v1 = MpCapable::parse(&mp_capable(1, &[&keys[..], &[0, 100]].concat())).unwrap();
+        assert_eq!((v1.sender_key, v1.receiver_key), (sender, receiver));
+        assert_eq!((v1.data_level_length, v1.checksum), (Some(100), None));
+        let v1 = MpCapable::parse(&mp_capable(1, &[&keys[..], &[0, 100, 0xab, 0xcd]].concat())).unwrap();
+        assert_eq!((v1.data_level_length, v1.checksum), (Some(100), Some(0xabcd)));
+        assert!(MpCapable::parse(&mp_capable(1, &keys[..4])).is_err());
+    }
+
+    #[test]
+    fn malformed_dss_chains_to_its_length_error() {
+        use std::error::Error;
+
+        let err = Dss::parse(&[0x20, DSS_DATA_ACK]).unwrap_err();
+        assert!(matches!(err, ParseError::MptcpSubtype(_)));
+        let source = err.source().and_then(|source| source.downcast_ref::<ParseError>());
+        assert_eq!(source, Some(&ParseError::InvalidLength { kind: 30, length: 4 }));
+    }
+}
@@ -0,0 +1,63 @@
+//! A flat, protobuf-friendly form of a single option for exporting to gRPC or
+//! other protobuf pipelines. It derives `prost::Message`, so it can be encoded
+//! directly or copied into a caller's own schema field by field.
+
+use crate::TcpOption;
+
+#[derive(Clone,PartialEq,prost::Message)]
+pub struct TcpOptionProto {
+    /// The option kind.
+    #[prost(uint32, tag = "1")]
+    pub kind: u32,
+    /// The value of options with a single numeric value. A timestamp packs its
+    /// value into the upper and its echo reply into the lower 32 bits.
+    #[prost(uint64, tag = "2")]
+    pub value_u64: u64,
+    /// The payload of every other option as it appears on the wire, such as
+    /// the 8-byte blocks of a SACK or the 16 bytes of an MD5 digest.
+    #[prost(bytes = "vec", tag = "3")]
+    pub bytes: Vec<u8>,
+}
+
+impl From<&TcpOption> for TcpOptionProto {
+    fn from(option: &TcpOption) -> Self {
+        let mut proto = TcpOptionProto { kind: option.kind() as u32, value_u64: 0, bytes: Vec::new() };
+        match option {
+            TcpOption::MaximumSegmentSize(value) | TcpOption::UserTimeout(value) => proto.value_u64 = *value as u64,
+            TcpOption::WindowScale(shift) => proto.value_u64 = shift.raw() as u64,
+            TcpOption::PartialOrderServiceProfile(value)
+            | TcpOption::AlternateChecksumRequest(value)
+            | TcpOption::TrailerChecksum(value) => proto.value_u64 = *value as u64,
+            TcpOption::Echo(value)
+            | TcpOption::EchoReply(value)
+            | TcpOption::ConnectionCount(value)
+            | TcpOption::ConnectionCountNew(value)
+            | TcpOption::ConnectionCountEcho(value) => proto.value_u64 = *value as u64,
+            TcpOption::QuickStartResponse(value) => proto.value_u64 = *value,
+            TcpOption::Timestamp(timestamp) => {
+                proto.value_u64 = (timestamp.value() as u64) << 32 | timestamp.echo_reply() as u64
+            }
+            TcpOption::EndOfOptionList | TcpOption::NoOperation => {}
+            option => proto.bytes = option.to_bytes().get(2..).map(<[u8]>::to_vec).unwrap_or_default(),
+        }
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sack;
+
+    #[test]
+    fn mss_and_sack_convert_to_proto() {
+        let mss = TcpOptionProto::from(&TcpOption::MaximumSegmentSize(1460));
+        assert_eq!(mss, TcpOptionProto { kind: 2, value_u64: 1460, bytes: vec![] });
+
+        let sack = TcpOptionProto::from(&TcpOption::Sack(vec![Sack::new(1, 2)]));
+        assert_eq!(sack, TcpOptionProto { kind: 5, value_u64: 0, bytes: vec![0, 0, 0, 1, 0, 0, 0, 2] });
+
+        let unknown = TcpOptionProto::from(&TcpOption::Unknown { kind: 1, length: None, data: vec![1, 2] });
+        assert_eq!(unknown, TcpOptionProto { kind: 1, value_u64: 0, bytes: vec![] });
+    }
+}
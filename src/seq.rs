@@ -0,0 +1,45 @@
+//! Comparisons of 32-bit TCP sequence numbers using serial number arithmetic
+//! (RFC 1982), so they stay correct when the sequence space wraps. Two numbers
+//! exactly 2^31 apart have no defined order: neither comes before the other.
+
+/// `a` comes before `b`.
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    let d = b.wrapping_sub(a);
+    d != 0 && d < 1 << 31
+}
+
+/// `a` comes before or is equal to `b`.
+pub fn seq_leq(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+/// `x` lies in the half-open range `lo..hi`, the same convention SACK blocks
+/// use for their left and right edges.
+pub fn seq_in_range(x: u32, lo: u32, hi: u32) -> bool {
+    x.wrapping_sub(lo) < hi.wrapping_sub(lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparisons_hold_across_the_wrap() {
+        assert!(seq_lt(u32::MAX, 0));
+        assert!(!seq_lt(0, u32::MAX));
+        assert!(seq_lt(0, (1 << 31) - 1));
+        assert!(!seq_lt(0, 1 << 31));
+        assert!(!seq_lt(1 << 31, 0));
+        assert!(!seq_lt(5, 5));
+
+        assert!(seq_leq(5, 5));
+        assert!(seq_leq(u32::MAX - 1, 1));
+        assert!(!seq_leq(1, u32::MAX - 1));
+        assert!(!seq_leq(0, 1 << 31));
+
+        assert!(seq_in_range(u32::MAX, u32::MAX - 1, 10));
+        assert!(seq_in_range(0, u32::MAX - 1, 10));
+        assert!(!seq_in_range(10, u32::MAX - 1, 10));
+        assert!(!seq_in_range(u32::MAX - 2, u32::MAX - 1, 10));
+    }
+}
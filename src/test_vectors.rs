@@ -0,0 +1,102 @@
+//! Captured options fields paired with the options `parse_options` is expected
+//! to decode them into. Downstream crates can feed these through their own
+//! integration to check they agree with this crate.
+
+use crate::{Sack, TcpOption, Timestamp, WindowShift};
+
+/// Linux SYN: MSS 1460, SACK permitted, timestamp, NOP, window scale 7.
+pub const LINUX_SYN: &[u8] = &[
+    0x02, 0x04, 0x05, 0xb4, // MSS 1460
+    0x04, 0x02, // SACK permitted
+    0x08, 0x0a, 0x00, 0x9a, 0x2b, 0x6c, 0x00, 0x00, 0x00, 0x00, // Timestamp
+    0x01, // NOP
+    0x03, 0x03, 0x07, // Window scale 7
+];
+
+pub fn linux_syn_expected() -> Vec<TcpOption> {
+    vec![
+        TcpOption::MaximumSegmentSize(1460),
+        TcpOption::SackPermitted,
+        TcpOption::Timestamp(Timestamp { value: 0x009a2b6c, echo_reply: 0 }),
+        TcpOption::NoOperation,
+        TcpOption::WindowScale(WindowShift(7)),
+    ]
+}
+
+/// Windows SYN: MSS 1460, NOP, window scale 8, NOP, NOP, SACK permitted.
+pub const WINDOWS_SYN: &[u8] = &[
+    0x02, 0x04, 0x05, 0xb4, // MSS 1460
+    0x01, // NOP
+    0x03, 0x03, 0x08, // Window scale 8
+    0x01, 0x01, // NOP, NOP
+    0x04, 0x02, // SACK permitted
+];
+
+pub fn windows_syn_expected() -> Vec<TcpOption> {
+    vec![
+        TcpOption::MaximumSegmentSize(1460),
+        TcpOption::NoOperation,
+        TcpOption::WindowScale(WindowShift(8)),
+        TcpOption::NoOperation,
+        TcpOption::NoOperation,
+        TcpOption::SackPermitted,
+    ]
+}
+
+/// macOS SYN: MSS 1460, NOP, window scale 6, NOP, NOP, timestamp, SACK
+/// permitted, then end of option list and zero padding.
+pub const MACOS_SYN: &[u8] = &[
+    0x02, 0x04, 0x05, 0xb4, // MSS 1460
+    0x01, // NOP
+    0x03, 0x03, 0x06, // Window scale 6
+    0x01, 0x01, // NOP, NOP
+    0x08, 0x0a, 0x3c, 0x1f, 0x52, 0x7e, 0x00, 0x00, 0x00, 0x00, // Timestamp
+    0x04, 0x02, // SACK permitted
+    0x00, 0x00, // End of option list, padding
+];
+
+pub fn macos_syn_expected() -> Vec<TcpOption> {
+    vec![
+        TcpOption::MaximumSegmentSize(1460),
+        TcpOption::NoOperation,
+        TcpOption::WindowScale(WindowShift(6)),
+        TcpOption::NoOperation,
+        TcpOption::NoOperation,
+        TcpOption::Timestamp(Timestamp { value: 0x3c1f527e, echo_reply: 0 }),
+        TcpOption::SackPermitted,
+        TcpOption::EndOfOptionList,
+    ]
+}
+
+/// MPTCP v0 MP_CAPABLE on a SYN, carrying the sender's 64-bit key.
+pub const MPTCP_MP_CAPABLE_SYN: &[u8] = &[
+    0x1e, 0x0c, // Multipath TCP, length 12
+    0x00, 0x81, // Subtype MP_CAPABLE, version 0, flags A|H
+    0x8b, 0x2f, 0x03, 0x6d, 0xc2, 0x11, 0x7a, 0x04, // Sender key
+];
+
+pub fn mptcp_mp_capable_syn_expected() -> Vec<TcpOption> {
+    vec![TcpOption::MultipathTCP(vec![
+        0x00, 0x81, 0x8b, 0x2f, 0x03, 0x6d, 0xc2, 0x11, 0x7a, 0x04,
+    ])]
+}
+
+/// Data segment acknowledging one out-of-order block: NOP, NOP, timestamp,
+/// NOP, NOP, SACK with a single block.
+pub const SACK_DATA_SEGMENT: &[u8] = &[
+    0x01, 0x01, // NOP, NOP
+    0x08, 0x0a, 0x00, 0x9a, 0x2f, 0x10, 0x5d, 0x04, 0x11, 0x8e, // Timestamp
+    0x01, 0x01, // NOP, NOP
+    0x05, 0x0a, 0x7a, 0x3b, 0x1c, 0x00, 0x7a, 0x3b, 0x21, 0xb4, // SACK, one block
+];
+
+pub fn sack_data_segment_expected() -> Vec<TcpOption> {
+    vec![
+        TcpOption::NoOperation,
+        TcpOption::NoOperation,
+        TcpOption::Timestamp(Timestamp { value: 0x009a2f10, echo_reply: 0x5d04118e }),
+        TcpOption::NoOperation,
+        TcpOption::NoOperation,
+        TcpOption::Sack(vec![Sack { left_edge: 0x7a3b1c00, right_edge: 0x7a3b21b4 }]),
+    ]
+}